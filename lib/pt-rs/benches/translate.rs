@@ -1,33 +1,88 @@
 #![feature(test)]
 extern crate test;
 
-use rand::Rng;
 use rand::seq::SliceRandom;
+use rand::Rng;
 use test::Bencher;
 
-use mapping::Prot;
-
-use pt::{Flags, PageTable};
+use pt::{Flags, PageTable, Prot, Tlb};
 
 const ENTRIES: usize = 2400;
 
-#[bench]
-fn bench_translation(b: &mut Bencher) {
+fn random() -> (PageTable, Vec<(u64, u64)>) {
     let mut addrs = vec![];
     let mut pt = PageTable::default();
 
     let mut rng = rand::thread_rng();
 
     for _ in 0..ENTRIES {
-        let vaddr = rng.gen::<u64>() & !0xfff;
-        let paddr = rng.gen::<u64>() & !0xfff;
+        let vaddr = rng.gen::<u64>() & 0x7fff_ffff_f000;
+        let paddr = rng.gen::<u64>() & 0x000f_ffff_ffff_f000;
+
+        pt.insert(vaddr, paddr, Flags::Present).unwrap();
+        addrs.push((vaddr, paddr));
+    }
+
+    (pt, addrs)
+}
 
-        pt.insert(vaddr, paddr, Flags::Present);
+fn sequential() -> (PageTable, Vec<(u64, u64)>) {
+    let mut addrs = vec![];
+    let mut pt = PageTable::default();
+
+    for ii in 0..ENTRIES as u64 {
+        let vaddr = 0x4000_0000 + ii * 0x1000;
+        let paddr = 0x8000_0000 + ii * 0x1000;
+
+        pt.insert(vaddr, paddr, Flags::Present).unwrap();
         addrs.push((vaddr, paddr));
     }
 
+    (pt, addrs)
+}
+
+#[bench]
+fn bench_translation(b: &mut Bencher) {
+    let (pt, addrs) = random();
+    let mut rng = rand::thread_rng();
+
     b.iter(|| {
         let (v, p) = addrs.choose(&mut rng).unwrap();
         assert_eq!(pt.translate(*v, Prot::R).unwrap(), *p);
     });
 }
+
+#[bench]
+fn bench_translation_cached(b: &mut Bencher) {
+    let (pt, addrs) = random();
+    let mut rng = rand::thread_rng();
+    let mut tlb = Tlb::new();
+
+    b.iter(|| {
+        let (v, p) = addrs.choose(&mut rng).unwrap();
+        assert_eq!(tlb.translate(&pt, *v, Prot::R).unwrap(), *p);
+    });
+}
+
+#[bench]
+fn bench_translation_sequential(b: &mut Bencher) {
+    let (pt, addrs) = sequential();
+
+    b.iter(|| {
+        for (v, p) in addrs.iter() {
+            assert_eq!(pt.translate(*v, Prot::R).unwrap(), *p);
+        }
+    });
+}
+
+#[bench]
+fn bench_translation_sequential_cached(b: &mut Bencher) {
+    let (pt, addrs) = sequential();
+    let mut tlb = Tlb::new();
+
+    b.iter(|| {
+        for (v, p) in addrs.iter() {
+            assert_eq!(tlb.translate(&pt, *v, Prot::R).unwrap(), *p);
+        }
+    });
+}
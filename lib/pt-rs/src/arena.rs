@@ -0,0 +1,526 @@
+//
+// Table arena
+//
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use memmap::MmapMut;
+
+use crate::{
+    commit_next, pd_index, pdpt_index, pml4_index, pml5_index, pt_index, table_data, Error, Level,
+    PADDR_MASK, RESERVED,
+};
+
+// the page size bit of 2mb and 1gb pages, in both x86 and EPT entries
+const LEAF_SIZE: u64 = 1 << 7;
+
+impl Level {
+    // the structure an entry at this level points at
+    pub(crate) fn below(self) -> Level {
+        match self {
+            Level::Pml5 => Level::Pml4,
+            Level::Pml4 => Level::Pdpt,
+            Level::Pdpt => Level::Pd,
+            Level::Pd => Level::Pt,
+            Level::Pt => unreachable!("entries in a pt only map pages"),
+        }
+    }
+
+    // the entry for addr in a table at this level
+    pub(crate) fn index(self, addr: u64) -> usize {
+        match self {
+            Level::Pml5 => pml5_index(addr),
+            Level::Pml4 => pml4_index(addr),
+            Level::Pdpt => pdpt_index(addr),
+            Level::Pd => pd_index(addr),
+            Level::Pt => pt_index(addr),
+        }
+    }
+
+    // bytes of address space covered by an entry at this level
+    pub(crate) fn span(self) -> u64 {
+        match self {
+            Level::Pml5 => 1 << 48,
+            Level::Pml4 => 1 << 39,
+            Level::Pdpt => 0x4000_0000,
+            Level::Pd => 0x20_0000,
+            Level::Pt => 0x1000,
+        }
+    }
+}
+
+// whether a non-empty entry maps memory, rather than pointing at a table
+pub(crate) fn is_leaf(level: Level, e: u64) -> bool {
+    match level {
+        Level::Pt => true,
+        Level::Pd | Level::Pdpt => e & LEAF_SIZE != 0,
+        Level::Pml4 | Level::Pml5 => false,
+    }
+}
+
+// whether a non-empty entry points at a table the walk goes on to, rather
+// than mapping memory or having reserved bits set
+pub(crate) fn is_table(level: Level, e: u64) -> bool {
+    e != 0 && !is_leaf(level, e) && e & RESERVED == 0
+}
+
+// the frame mapped by a leaf
+pub(crate) fn leaf_addr(level: Level, e: u64) -> u64 {
+    e & PADDR_MASK & !(level.span() - 1)
+}
+
+// the level of the largest page both addresses are aligned for, which fits
+// in `left` bytes
+fn page_level(addr: u64, paddr: u64, left: u64) -> Level {
+    if (addr | paddr) & 0x3fff_ffff == 0 && left >= 0x4000_0000 {
+        Level::Pdpt
+    } else if (addr | paddr) & 0x1f_ffff == 0 && left >= 0x20_0000 {
+        Level::Pd
+    } else {
+        Level::Pt
+    }
+}
+
+// the pages mapping `len` bytes from `paddr` at `addr`, as the address,
+// physical address and level of each, using 1gb and 2mb pages wherever the
+// alignment of both addresses allows
+pub(crate) fn pages(addr: u64, paddr: u64, len: u64) -> impl Iterator<Item = (u64, u64, Level)> {
+    let mut off = 0;
+
+    std::iter::from_fn(move || {
+        if off >= len {
+            return None;
+        }
+
+        let (a, p) = (addr + off, paddr + off);
+        let level = page_level(a, p, len - off);
+
+        off += level.span();
+        Some((a, p, level))
+    })
+}
+
+// the slot of the table an entry points at
+pub(crate) fn child(e: u64) -> usize {
+    ((e & PADDR_MASK) >> 12) as usize
+}
+
+// the address bits of an entry pointing at the table in `slot`
+pub(crate) fn table_entry(slot: usize) -> u64 {
+    (slot as u64) << 12
+}
+
+// Table pages kept back to back in the format commit writes, except that
+// entries pointing at a table hold its slot in the arena rather than a
+// physical address. Slot 0 is the root. Empty entries are zero, so entries
+// without the present bit still count as used. Table entries read from
+// memory with reserved bits set hold `RESERVED` instead of a slot, and are
+// committed as they were read.
+#[derive(Clone)]
+pub(crate) struct Arena {
+    pages: Vec<[u64; 512]>,
+    // the entries read from memory in place of each `RESERVED` entry, by slot
+    // and index
+    reserved: BTreeMap<(usize, usize), u64>,
+    // the level of the table in each slot, or None once it's freed
+    levels: Vec<Option<Level>>,
+    free: Vec<usize>,
+    // slots written since the last clear_dirty
+    dirty: BTreeSet<usize>,
+}
+
+impl Arena {
+    pub(crate) fn new(root: Level) -> Self {
+        Self {
+            pages: vec![[0; 512]],
+            reserved: BTreeMap::new(),
+            levels: vec![Some(root)],
+            free: vec![],
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    pub(crate) fn root(&self) -> Level {
+        self.levels[0].unwrap()
+    }
+
+    // number of live table pages
+    pub(crate) fn tables(&self) -> usize {
+        self.pages.len() - self.free.len()
+    }
+
+    // number of slots, live or free
+    pub(crate) fn slots(&self) -> usize {
+        self.pages.len()
+    }
+
+    // the slots written since the last clear_dirty, whose tables need
+    // committing again
+    pub(crate) fn dirty(&self) -> Vec<usize> {
+        self.dirty.iter().copied().collect()
+    }
+
+    pub(crate) fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
+
+    pub(crate) fn page(&self, slot: usize) -> &[u64; 512] {
+        &self.pages[slot]
+    }
+
+    pub(crate) fn get(&self, slot: usize, idx: usize) -> u64 {
+        self.pages[slot][idx]
+    }
+
+    pub(crate) fn set(&mut self, slot: usize, idx: usize, e: u64) {
+        if self.pages[slot][idx] != e {
+            if self.pages[slot][idx] & RESERVED != 0 {
+                self.reserved.remove(&(slot, idx));
+            }
+
+            self.pages[slot][idx] = e;
+            self.dirty.insert(slot);
+        }
+    }
+
+    // store a table entry read from memory with reserved bits set, as `e`
+    // holding its flags and `RESERVED`
+    pub(crate) fn set_reserved(&mut self, slot: usize, idx: usize, raw: u64, e: u64) {
+        self.set(slot, idx, e | RESERVED);
+        self.reserved.insert((slot, idx), raw);
+    }
+
+    pub(crate) fn alloc(&mut self, level: Level) -> usize {
+        match self.free.pop() {
+            Some(slot) => {
+                self.levels[slot] = Some(level);
+                slot
+            }
+            None => {
+                self.pages.push([0; 512]);
+                self.levels.push(Some(level));
+                self.pages.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, slot: usize) {
+        self.reserved.retain(|&(ss, _), _| ss != slot);
+        self.pages[slot] = [0; 512];
+        self.levels[slot] = None;
+        self.dirty.insert(slot);
+        self.free.push(slot);
+    }
+
+    // free the table in `slot` and every table below it
+    fn free_tree(&mut self, slot: usize) {
+        let level = self.levels[slot].unwrap();
+
+        for ii in 0..512 {
+            let e = self.pages[slot][ii];
+
+            if is_table(level, e) {
+                self.free_tree(child(e));
+            }
+        }
+
+        self.release(slot);
+    }
+
+    // the table at `level` on the walk to addr, if the walk gets that far
+    pub(crate) fn table(&self, addr: u64, level: Level) -> Option<usize> {
+        let (mut slot, mut at) = (0, self.root());
+
+        if level < at {
+            return None;
+        }
+
+        while at != level {
+            let e = self.pages[slot][at.index(addr)];
+
+            if !is_table(at, e) {
+                return None;
+            }
+
+            slot = child(e);
+            at = at.below();
+        }
+
+        Some(slot)
+    }
+
+    // the slot, level and value of the entry the walk to addr ends at, which
+    // is either a leaf, empty or has reserved bits set
+    pub(crate) fn find(&self, addr: u64) -> (usize, Level, u64) {
+        let (mut slot, mut level) = (0, self.root());
+
+        loop {
+            let e = self.pages[slot][level.index(addr)];
+
+            if !is_table(level, e) {
+                return (slot, level, e);
+            }
+
+            slot = child(e);
+            level = level.below();
+        }
+    }
+
+    // the tables on the walk to addr, from the root down to the one holding
+    // the entry the walk ends at
+    pub(crate) fn path(&self, addr: u64) -> Vec<(usize, Level)> {
+        let (mut slot, mut level) = (0, self.root());
+        let mut r = vec![];
+
+        loop {
+            r.push((slot, level));
+            let e = self.pages[slot][level.index(addr)];
+
+            if !is_table(level, e) {
+                return r;
+            }
+
+            slot = child(e);
+            level = level.below();
+        }
+    }
+
+    // walk to the table at `until` for addr, creating the tables on the way
+    // and replacing any leaves on the way with empty tables. `flags` gives
+    // the flag bits of each table entry on the walk from its current ones, or
+    // from None for a new table. Returns the slot of the table.
+    pub(crate) fn walk_to<F>(&mut self, addr: u64, until: Level, mut flags: F) -> usize
+    where
+        F: FnMut(Option<u64>) -> u64,
+    {
+        let (mut slot, mut level) = (0, self.root());
+
+        while level != until {
+            let idx = level.index(addr);
+            let e = self.pages[slot][idx];
+
+            let (next, f) = match !is_table(level, e) {
+                true => (self.alloc(level.below()), flags(None)),
+                false => (child(e), flags(Some(e & !PADDR_MASK))),
+            };
+
+            self.set(slot, idx, table_entry(next) | f);
+
+            slot = next;
+            level = level.below();
+        }
+
+        slot
+    }
+
+    // replace the large page for addr in the table at `slot` with a table of
+    // the next smaller pages mapping the same memory. `bits` gives the flag
+    // bits of each new entry and of the entry pointing at the table, from the
+    // level and value of the large page.
+    pub(crate) fn split<F>(&mut self, slot: usize, level: Level, addr: u64, bits: F)
+    where
+        F: Fn(Level, u64) -> (u64, u64),
+    {
+        let idx = level.index(addr);
+        let e = self.pages[slot][idx];
+        let frame = leaf_addr(level, e);
+        let below = level.below();
+        let (leaf, table) = bits(level, e);
+
+        let next = self.alloc(below);
+        for ii in 0..512 {
+            self.set(next, ii, (frame + ii as u64 * below.span()) | leaf);
+        }
+
+        self.set(slot, idx, table_entry(next) | table);
+    }
+
+    // split the large pages on the walk to addr above `level` with `bits`,
+    // failing if it goes through an entry with reserved bits
+    pub(crate) fn split_to<F>(&mut self, addr: u64, level: Level, bits: F) -> Result<(), Error>
+    where
+        F: Fn(Level, u64) -> (u64, u64),
+    {
+        loop {
+            if self.reserved_above(addr, level) {
+                return Err(Error::Reserved(addr));
+            }
+
+            let (slot, at, e) = self.find(addr);
+
+            if e == 0 || at >= level {
+                return Ok(());
+            }
+
+            self.split(slot, at, addr, &bits);
+        }
+    }
+
+    // whether the walk to a page at `level` for addr goes through an entry
+    // with reserved bits
+    pub(crate) fn reserved_above(&self, addr: u64, level: Level) -> bool {
+        let (_, at, e) = self.find(addr);
+
+        e & RESERVED != 0 && at < level
+    }
+
+    // the first address in [start, end) whose walk ends at an entry `mapped`
+    // accepts, given its level and value
+    pub(crate) fn mapped_in<F>(&self, start: u64, end: u64, mapped: F) -> Option<u64>
+    where
+        F: Fn(Level, u64) -> bool,
+    {
+        let mut addr = start;
+
+        while addr < end {
+            let (_, level, e) = self.find(addr);
+
+            if mapped(level, e) {
+                return Some(addr);
+            }
+
+            addr = (addr | (level.span() - 1)).saturating_add(1);
+        }
+
+        None
+    }
+
+    // check that a page at `level` for addr wouldn't replace a table with
+    // smaller pages in it
+    pub(crate) fn check_replace<F>(&self, addr: u64, level: Level, mapped: F) -> Result<(), Error>
+    where
+        F: Fn(Level, u64) -> bool,
+    {
+        let (_, at, _) = self.find(addr);
+
+        if at <= level {
+            return Ok(());
+        }
+
+        let end = addr.saturating_add(level.span());
+        match self.mapped_in(addr, end, mapped) {
+            Some(mapped) => Err(Error::Overlap(mapped)),
+            None => Ok(()),
+        }
+    }
+
+    // store a leaf, freeing the tables below the entry it replaces
+    pub(crate) fn set_leaf(&mut self, slot: usize, level: Level, addr: u64, e: u64) {
+        let idx = level.index(addr);
+        let old = self.pages[slot][idx];
+
+        if is_table(level, old) {
+            self.free_tree(child(old));
+        }
+
+        self.set(slot, idx, e);
+    }
+
+    // clear the leaf the walk to addr ends at, freeing any tables left empty.
+    // Returns whether there was one.
+    pub(crate) fn remove(&mut self, addr: u64) -> bool {
+        let path = self.path(addr);
+        let (slot, level) = path[path.len() - 1];
+        let idx = level.index(addr);
+
+        if self.pages[slot][idx] == 0 {
+            return false;
+        }
+
+        self.set(slot, idx, 0);
+
+        for w in path.windows(2).rev() {
+            let ((parent, level), (slot, _)) = (w[0], w[1]);
+
+            if self.pages[slot].iter().any(|&e| e != 0) {
+                break;
+            }
+
+            self.release(slot);
+            self.set(parent, level.index(addr), 0);
+        }
+
+        true
+    }
+
+    // every non-empty leaf, with the level of its table
+    pub(crate) fn leaves(&self) -> impl Iterator<Item = (Level, u64)> + '_ {
+        (0..self.pages.len()).flat_map(move |ss| self.leaves_in(ss))
+    }
+
+    // the non-empty leaves in the table in `slot`
+    pub(crate) fn leaves_in(&self, slot: usize) -> impl Iterator<Item = (Level, u64)> + '_ {
+        let level = self.levels[slot];
+
+        self.pages[slot].iter().filter_map(move |&e| match level {
+            Some(level) if e != 0 && is_leaf(level, e) => Some((level, e)),
+            _ => None,
+        })
+    }
+
+    // the physical address of each slot when committed from `base`, where
+    // live tables are laid out in slot order. The root is at `base`.
+    fn layout(&self, base: u64) -> Vec<u64> {
+        let mut next = base;
+
+        self.levels
+            .iter()
+            .map(|level| match level {
+                Some(_) => commit_next(&mut next),
+                None => 0,
+            })
+            .collect()
+    }
+
+    // the table in `slot` as committed, with each entry pointing at a table
+    // rewritten to the physical address `paddr` gives its slot
+    pub(crate) fn committed_page<F>(&self, slot: usize, paddr: F) -> [u64; 512]
+    where
+        F: Fn(usize) -> u64,
+    {
+        let mut data = self.pages[slot];
+
+        if let Some(level) = self.levels[slot] {
+            for (ii, e) in data.iter_mut().enumerate() {
+                if is_table(level, *e) {
+                    *e = (*e & !PADDR_MASK) | paddr(child(*e));
+                } else if *e & RESERVED != 0 {
+                    *e = self.reserved[&(slot, ii)];
+                }
+            }
+        }
+
+        data
+    }
+
+    // the live tables in slot order, along with where they land
+    pub(crate) fn committed(&self, base: u64) -> impl Iterator<Item = (u64, [u64; 512])> + '_ {
+        let paddrs = self.layout(base);
+
+        (0..self.pages.len())
+            .filter(move |&ss| self.levels[ss].is_some())
+            .map(move |ss| (paddrs[ss], self.committed_page(ss, |x| paddrs[x])))
+    }
+
+    // copy out the tables from `base`, a page at a time
+    pub(crate) fn commit(&self, base: u64) -> Result<BTreeMap<u64, MmapMut>, io::Error> {
+        let mut r = BTreeMap::new();
+
+        for (paddr, data) in self.committed(base) {
+            let mut backing = MmapMut::map_anon(0x1000)?;
+            table_data(&mut backing).copy_from_slice(&data);
+
+            r.insert(paddr, backing);
+        }
+
+        Ok(r)
+    }
+
+    // copy out the tables from `base` back to back into `out`, which holds
+    // all of them
+    pub(crate) fn commit_into(&self, base: u64, out: &mut [u64]) {
+        for (page, (_, data)) in out.chunks_mut(512).zip(self.committed(base)) {
+            page.copy_from_slice(&data);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//
+// Comparing tables
+//
+use crate::{contiguous, Coalesce, Flags, Mapping, PageTable};
+
+/// How the mappings of one table differ from another's, see
+/// `PageTable::diff`. Each list is sorted by virtual address, with
+/// contiguous ranges merged, and each changed range is in exactly one list.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diff {
+    /// Mapped only by the other table
+    pub added: Vec<Mapping>,
+    /// Mapped only by this table
+    pub removed: Vec<Mapping>,
+    /// Mapped by both to different physical memory, as this table's mapping
+    /// followed by the other's. The flags may differ too.
+    pub remapped: Vec<(Mapping, Mapping)>,
+    /// Mapped by both to the same physical memory with different flags, as
+    /// this table's mapping followed by the other's
+    pub reprotected: Vec<(Mapping, Mapping)>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.remapped.is_empty()
+            && self.reprotected.is_empty()
+    }
+}
+
+impl PageTable {
+    /// The changes turning the mappings of this table into those of `other`,
+    /// whatever size of page either uses. Accessed and dirty bits are
+    /// ignored, as the cpu sets them on every access.
+    pub fn diff(&self, other: &PageTable) -> Diff {
+        let mut old = ranges(self);
+        let mut new = ranges(other);
+        let (mut a, mut b) = (old.next(), new.next());
+        let mut r = Diff::default();
+
+        loop {
+            match (a, b) {
+                (None, None) => break,
+                (Some(x), None) => {
+                    push(&mut r.removed, x);
+                    a = old.next();
+                }
+                (None, Some(y)) => {
+                    push(&mut r.added, y);
+                    b = new.next();
+                }
+                // whichever starts first is only mapped by its table until
+                // the other starts
+                (Some(x), Some(y)) if x.vaddr < y.vaddr => {
+                    let size = x.size.min(y.vaddr - x.vaddr);
+                    push(&mut r.removed, piece(&x, 0, size));
+                    a = rest(&x, size).or_else(|| old.next());
+                }
+                (Some(x), Some(y)) if y.vaddr < x.vaddr => {
+                    let size = y.size.min(x.vaddr - y.vaddr);
+                    push(&mut r.added, piece(&y, 0, size));
+                    b = rest(&y, size).or_else(|| new.next());
+                }
+                (Some(x), Some(y)) => {
+                    let size = x.size.min(y.size);
+                    let pair = (piece(&x, 0, size), piece(&y, 0, size));
+
+                    if pair.0.paddr != pair.1.paddr {
+                        push_pair(&mut r.remapped, pair);
+                    } else if pair.0.flags != pair.1.flags {
+                        push_pair(&mut r.reprotected, pair);
+                    }
+
+                    a = rest(&x, size).or_else(|| old.next());
+                    b = rest(&y, size).or_else(|| new.next());
+                }
+            }
+        }
+
+        r
+    }
+}
+
+// the coalesced mappings of a table, without accessed and dirty bits
+fn ranges(x: &PageTable) -> impl Iterator<Item = Mapping> + '_ {
+    let mappings = x.mappings().map(|mut m| {
+        m.flags -= Flags::Accessed | Flags::Dirty;
+        m
+    });
+
+    Coalesce {
+        inner: mappings.peekable(),
+    }
+}
+
+// `size` bytes of a mapping from `off`
+fn piece(m: &Mapping, off: u64, size: u64) -> Mapping {
+    Mapping {
+        vaddr: m.vaddr + off,
+        paddr: m.paddr + off,
+        size,
+        flags: m.flags,
+    }
+}
+
+// what's left of a mapping after its first `off` bytes
+fn rest(m: &Mapping, off: u64) -> Option<Mapping> {
+    match off < m.size {
+        true => Some(piece(m, off, m.size - off)),
+        false => None,
+    }
+}
+
+fn push(v: &mut Vec<Mapping>, m: Mapping) {
+    match v.last_mut() {
+        Some(last) if contiguous(last, &m) => last.size += m.size,
+        _ => v.push(m),
+    }
+}
+
+fn push_pair(v: &mut Vec<(Mapping, Mapping)>, pair: (Mapping, Mapping)) {
+    match v.last_mut() {
+        Some(last) if contiguous(&last.0, &pair.0) && contiguous(&last.1, &pair.1) => {
+            last.0.size += pair.0.size;
+            last.1.size += pair.1.size;
+        }
+        _ => v.push(pair),
+    }
+}
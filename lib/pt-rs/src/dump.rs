@@ -0,0 +1,370 @@
+//
+// Dumping tables
+//
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use memmap::MmapMut;
+
+use crate::arena::is_leaf;
+use crate::{
+    check_phys_bits, committed_reader, read_table, reserved_bits, Level, PageFault, PageTable,
+    Paging, Prot, PADDR_MASK,
+};
+
+// the flags decoded for each kind of entry, by bit
+const TABLE_NAMES: [(u64, &str); 7] = [
+    (0, "P"),
+    (1, "W"),
+    (2, "U"),
+    (3, "PWT"),
+    (4, "PCD"),
+    (5, "A"),
+    (63, "NX"),
+];
+
+const PTE_NAMES: [(u64, &str); 10] = [
+    (0, "P"),
+    (1, "W"),
+    (2, "U"),
+    (3, "PWT"),
+    (4, "PCD"),
+    (5, "A"),
+    (6, "D"),
+    (7, "PAT"),
+    (8, "G"),
+    (63, "NX"),
+];
+
+const LARGE_NAMES: [(u64, &str); 11] = [
+    (0, "P"),
+    (1, "W"),
+    (2, "U"),
+    (3, "PWT"),
+    (4, "PCD"),
+    (5, "A"),
+    (6, "D"),
+    (7, "PS"),
+    (8, "G"),
+    (12, "PAT"),
+    (63, "NX"),
+];
+
+/// The table pages reachable from a root as they are laid out in memory,
+/// which print as a listing of every present entry like `ptdump`: its index,
+/// raw value, decoded flags and the virtual addresses it covers
+pub struct Dump {
+    paging: Paging,
+    phys_bits: u8,
+    root: u64,
+    tables: BTreeMap<u64, [u64; 512]>,
+}
+
+impl PageTable {
+    /// Dump the tables as `commit` lays them out
+    pub fn dump(&self) -> Dump {
+        self.dump_at(0)
+    }
+
+    /// Dump the tables as they'd be committed from `base`
+    pub fn dump_at(&self, base: u64) -> Dump {
+        Dump {
+            paging: self.paging,
+            phys_bits: self.phys_bits,
+            root: base,
+            tables: self.arena.committed(base).collect(),
+        }
+    }
+
+    /// The walk for `vaddr`, followed by the outcome of reading, writing and
+    /// executing it as a supervisor
+    pub fn explain(&self, vaddr: u64) -> Explain {
+        let mut r = self.dump().explain(vaddr);
+
+        r.access = [Prot::R, Prot::W, Prot::X]
+            .iter()
+            .map(|&p| (p, self.translate_detailed(vaddr, p)))
+            .collect();
+
+        r
+    }
+}
+
+impl Dump {
+    /// Read the tables reachable from `cr3` out of guest physical memory for
+    /// a cpu with `phys_bits` of physical address, see
+    /// `PageTable::from_physical_with`. Only a failure to read the root or an
+    /// invalid `phys_bits` is an error, other tables which can't be read are
+    /// listed as such.
+    pub fn from_physical<F>(
+        paging: Paging,
+        phys_bits: u8,
+        cr3: u64,
+        mut read: F,
+    ) -> Result<Self, io::Error>
+    where
+        F: FnMut(u64, &mut [u8]) -> Result<(), io::Error>,
+    {
+        check_phys_bits(phys_bits)?;
+
+        let mut r = Self {
+            paging,
+            phys_bits,
+            root: cr3 & PADDR_MASK,
+            tables: BTreeMap::new(),
+        };
+
+        let root = read_table(&mut read, r.root)?;
+        r.tables.insert(r.root, root);
+        r.read_tables(r.root_level(), &root, &mut read);
+
+        Ok(r)
+    }
+
+    /// Dump the pages returned by `commit`
+    pub fn from_committed(
+        paging: Paging,
+        phys_bits: u8,
+        cr3: u64,
+        tables: &BTreeMap<u64, MmapMut>,
+    ) -> Result<Self, io::Error> {
+        Self::from_physical(paging, phys_bits, cr3, committed_reader(tables))
+    }
+
+    fn read_tables<F>(&mut self, level: Level, entries: &[u64; 512], read: &mut F)
+    where
+        F: FnMut(u64, &mut [u8]) -> Result<(), io::Error>,
+    {
+        for &e in entries.iter() {
+            let paddr = e & PADDR_MASK;
+
+            if !self.points_at_table(level, e) || self.tables.contains_key(&paddr) {
+                continue;
+            }
+
+            if let Ok(table) = read_table(read, paddr) {
+                self.tables.insert(paddr, table);
+                self.read_tables(level.below(), &table, read);
+            }
+        }
+    }
+
+    fn root_level(&self) -> Level {
+        match self.paging {
+            Paging::Level4 => Level::Pml4,
+            Paging::Level5 => Level::Pml5,
+        }
+    }
+
+    // whether the cpu would go on to read the table an entry points at
+    fn points_at_table(&self, level: Level, e: u64) -> bool {
+        e & 1 != 0 && !is_leaf(level, e) && !self.reserved(level, e)
+    }
+
+    fn reserved(&self, level: Level, e: u64) -> bool {
+        reserved_bits(level, e, self.phys_bits) != 0
+    }
+
+    /// The value to load into CR3
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// Walk the tables for `vaddr` without any permission checks
+    pub fn explain(&self, vaddr: u64) -> Explain {
+        let mut r = Explain {
+            vaddr,
+            steps: vec![],
+            end: End::NonCanonical,
+            access: vec![],
+        };
+
+        if !self.paging.is_canonical(vaddr) {
+            return r;
+        }
+
+        let (mut table, mut level) = (self.root, self.root_level());
+
+        r.end = loop {
+            let page = match self.tables.get(&table) {
+                Some(page) => page,
+                None => break End::Unread(table),
+            };
+
+            let idx = level.index(vaddr);
+            let e = page[idx];
+
+            r.steps.push(Step {
+                level,
+                idx,
+                gpa: table + idx as u64 * 8,
+                e,
+                desc: self.describe(level, e),
+            });
+
+            if e & 1 == 0 {
+                break End::NotPresent;
+            }
+
+            if self.reserved(level, e) {
+                break End::Reserved;
+            }
+
+            if is_leaf(level, e) {
+                let paddr = e & PADDR_MASK & !(level.span() - 1);
+                break End::Page(paddr + (vaddr & (level.span() - 1)), level.span());
+            }
+
+            table = e & PADDR_MASK;
+            level = level.below();
+        };
+
+        r
+    }
+
+    // the names of the flags set in an entry
+    fn describe(&self, level: Level, e: u64) -> String {
+        let names: &[(u64, &str)] = match (is_leaf(level, e), level) {
+            (false, _) => &TABLE_NAMES,
+            (true, Level::Pt) => &PTE_NAMES,
+            (true, _) => &LARGE_NAMES,
+        };
+
+        let mut r: Vec<String> = names
+            .iter()
+            .filter(|(bit, _)| e & 1 << bit != 0)
+            .map(|(_, name)| name.to_string())
+            .collect();
+
+        let key = (e >> 59) & 0xf;
+        if is_leaf(level, e) && key != 0 {
+            r.push(format!("PK{}", key));
+        }
+
+        if self.reserved(level, e) {
+            r.push("RSVD".to_string());
+        }
+
+        r.join(" ")
+    }
+
+    fn fmt_table(
+        &self,
+        f: &mut fmt::Formatter,
+        table: u64,
+        level: Level,
+        base: u64,
+        depth: usize,
+    ) -> fmt::Result {
+        let page = &self.tables[&table];
+        let indent = depth * 2;
+
+        for (idx, &e) in page.iter().enumerate().filter(|(_, e)| *e & 1 != 0) {
+            let start = self.paging.canonical(base + idx as u64 * level.span());
+            let end = start + (level.span() - 1);
+            let desc = self.describe(level, e);
+            let name = format!("{:indent$}{}[{:03x}]", "", level, idx, indent = indent);
+
+            write!(
+                f,
+                "{:<14} {:#018x} {:<20} {:#018x}-{:#018x}",
+                name, e, desc, start, end
+            )?;
+
+            if is_leaf(level, e) && !self.reserved(level, e) {
+                let paddr = e & PADDR_MASK & !(level.span() - 1);
+                writeln!(f, " -> {:#x}", paddr)?;
+                continue;
+            }
+
+            writeln!(f)?;
+
+            let child = e & PADDR_MASK;
+            if !self.points_at_table(level, e) {
+                continue;
+            }
+
+            match self.tables.contains_key(&child) {
+                true => self.fmt_table(f, child, level.below(), start, depth + 1)?,
+                false => writeln!(
+                    f,
+                    "{:indent$}  table at {:#x} not read",
+                    "",
+                    child,
+                    indent = indent
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} @ {:#x}", self.root_level(), self.root)?;
+        self.fmt_table(f, self.root, self.root_level(), 0, 0)
+    }
+}
+
+struct Step {
+    level: Level,
+    idx: usize,
+    // the address of the entry
+    gpa: u64,
+    e: u64,
+    desc: String,
+}
+
+enum End {
+    NonCanonical,
+    NotPresent,
+    Reserved,
+    Unread(u64),
+    // the physical address and size of the page
+    Page(u64, u64),
+}
+
+/// The entries used to translate an address, see `Dump::explain`
+pub struct Explain {
+    vaddr: u64,
+    steps: Vec<Step>,
+    end: End,
+    access: Vec<(Prot, Result<u64, PageFault>)>,
+}
+
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:#x}", self.vaddr)?;
+
+        for x in self.steps.iter() {
+            let name = format!("{}[{:03x}]", x.level, x.idx);
+            let line = format!("  {:<10} @ {:#x}: {:#018x} {}", name, x.gpa, x.e, x.desc);
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        match self.end {
+            End::NonCanonical => writeln!(f, "  not canonical")?,
+            End::NotPresent => writeln!(f, "  not present")?,
+            End::Reserved => writeln!(f, "  reserved bits set")?,
+            End::Unread(table) => writeln!(f, "  table at {:#x} not read", table)?,
+            End::Page(paddr, size) => writeln!(f, "  -> {:#x} ({:#x} byte page)", paddr, size)?,
+        }
+
+        for (p, r) in self.access.iter() {
+            let name = match *p {
+                Prot::W => "write",
+                Prot::X => "execute",
+                _ => "read",
+            };
+
+            match r {
+                Ok(_) => writeln!(f, "  {}: ok", name)?,
+                Err(pf) => writeln!(f, "  {}: {}", name, pf)?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,416 @@
+//
+// Extended page tables
+//
+use std::collections::BTreeMap;
+use std::fmt;
+
+use memmap::MmapMut;
+
+use crate::arena::{self, child, is_leaf, leaf_addr, Arena};
+use crate::{
+    check_paddr, check_range, commit_region, overlaps, pt_index, Error, Level, MemoryType, Prot,
+    TableRegion, MAX_PHYS_BITS, PADDR_MASK,
+};
+
+bitflags! {
+    /// The permissions and attributes of an EPT entry
+    pub struct EptFlags : u64 {
+        const Read = 1 << 0;
+        const Write = 1 << 1;
+        /// Supervisor mode execute with mode-based execute control, otherwise
+        /// all execute
+        const Execute = 1 << 2;
+        /// The memory type of a leaf, see `with_memory_type`
+        const MemoryType = 0b111 << 3;
+        const IgnorePat = 1 << 6;
+        const Accessed = 1 << 8;
+        const Dirty = 1 << 9;
+        /// User mode execute, only used with mode-based execute control, see
+        /// `set_mode_based_execute`
+        const UserExecute = 1 << 10;
+        const SuppressVe = 1 << 63;
+    }
+}
+
+impl EptFlags {
+    /// Set the memory type of the pages mapped with these flags. Returns
+    /// `None` for UC-, which EPT can't express.
+    pub fn with_memory_type(self, ty: MemoryType) -> Option<EptFlags> {
+        if ty == MemoryType::UncacheableMinus {
+            return None;
+        }
+
+        let f = self - EptFlags::MemoryType;
+        Some(f | EptFlags::from_bits_truncate(ty.encoding() << 3))
+    }
+
+    /// The memory type of a leaf, or `None` for the reserved encodings
+    pub fn memory_type(self) -> Option<MemoryType> {
+        match (self & EptFlags::MemoryType).bits() >> 3 {
+            7 => None,
+            x => MemoryType::from_encoding(x),
+        }
+    }
+
+    // the bits of a non-leaf entry, where the memory type bits are reserved
+    fn table(self) -> u64 {
+        (self & (EptFlags::Read | EptFlags::Write | EptFlags::Execute | EptFlags::UserExecute))
+            .bits()
+    }
+}
+
+// the page size bit of 2mb and 1gb pages
+const EPT_LARGE: u64 = 1 << 7;
+
+// the width of the guest physical addresses a 4-level EPT translates
+const GPA_BITS: u8 = 48;
+
+/// EPTP bit enabling accessed and dirty flags
+pub const EPTP_ACCESSED_DIRTY: u64 = 1 << 6;
+
+// a write back, 4-level eptp for the pml4 at `paddr`
+const fn eptp(paddr: u64) -> u64 {
+    paddr | (3 << 3) | 6
+}
+
+// whether an entry allows any access. User execute only counts with
+// mode-based execute control, otherwise bit 10 is ignored.
+fn present(e: u64, mbec: bool) -> bool {
+    let mut access = EptFlags::Read | EptFlags::Write | EptFlags::Execute;
+    access.set(EptFlags::UserExecute, mbec);
+
+    EptFlags::from_bits_truncate(e).intersects(access)
+}
+
+// the bits of the entries of a table splitting the 2mb or 1gb page `e`, and
+// of the entry pointing at it
+fn split_bits(level: Level, e: u64) -> (u64, u64) {
+    let f = e & !PADDR_MASK;
+
+    let leaf = match level.below() {
+        Level::Pt => f & !EPT_LARGE,
+        _ => f,
+    };
+
+    (leaf, EptFlags::from_bits_truncate(e).table())
+}
+
+// writable entries which aren't readable, and leaves with a reserved memory
+// type, cause EPT misconfigurations rather than violations
+fn misconfigured(level: Level, e: u64) -> bool {
+    let f = EptFlags::from_bits_truncate(e);
+    let leaf = level == Level::Pt || e & EPT_LARGE != 0;
+
+    (f.contains(EptFlags::Write) && !f.contains(EptFlags::Read))
+        || (leaf && f.memory_type().is_none())
+}
+
+/// An EPT violation or misconfiguration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EptViolation {
+    pub gpa: u64,
+    pub access: Prot,
+    /// The table holding the entry which caused the violation
+    pub level: Level,
+    /// Set for permission violations, clear if the entry wasn't present
+    pub present: bool,
+    /// Set if the entry is misconfigured, which exits with an EPT
+    /// misconfiguration instead of a violation
+    pub misconfigured: bool,
+}
+
+impl fmt::Display for EptViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.misconfigured {
+            true => "misconfiguration",
+            false => "violation",
+        };
+
+        write!(f, "EPT {} at {:#x} in {}", kind, self.gpa, self.level)
+    }
+}
+
+impl std::error::Error for EptViolation {}
+
+// The entries seen while translating a guest physical address, checked the
+// same way as a guest walk: missing and misconfigured entries stop the walk,
+// and permissions are checked once it's complete.
+struct EptWalk {
+    gpa: u64,
+    p: Prot,
+    user: bool,
+    mbec: bool,
+    entries: [(Level, u64); 4],
+    len: usize,
+}
+
+impl EptWalk {
+    fn new(gpa: u64, p: Prot, user: bool, mbec: bool) -> Self {
+        Self {
+            gpa,
+            p,
+            user,
+            mbec,
+            entries: [(Level::Pml4, 0); 4],
+            len: 0,
+        }
+    }
+
+    fn violation(&self, level: Level, e: u64) -> EptViolation {
+        EptViolation {
+            gpa: self.gpa,
+            access: self.p,
+            level,
+            present: present(e, self.mbec),
+            misconfigured: present(e, self.mbec) && misconfigured(level, e),
+        }
+    }
+
+    fn entry(&mut self, level: Level, e: u64) -> Result<(), EptViolation> {
+        if !present(e, self.mbec) || misconfigured(level, e) {
+            return Err(self.violation(level, e));
+        }
+
+        self.entries[self.len] = (level, e);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), EptViolation> {
+        let exec = match self.user && self.mbec {
+            true => EptFlags::UserExecute,
+            false => EptFlags::Execute,
+        };
+
+        let mut needed = EptFlags::empty();
+        needed.set(EptFlags::Read, self.p.contains(Prot::R));
+        needed.set(EptFlags::Write, self.p.contains(Prot::W));
+        needed.set(exec, self.p.contains(Prot::X));
+
+        let lacking = self.entries[..self.len]
+            .iter()
+            .find(|(_, e)| !EptFlags::from_bits_truncate(*e).contains(needed));
+
+        match lacking {
+            Some((level, e)) => Err(self.violation(*level, *e)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 4-level extended page tables, translating guest physical addresses to
+/// host physical addresses
+pub struct EptTable {
+    // the root is the pml4. As with PageTable, entries pointing at a table
+    // hold its slot in the arena until commit.
+    arena: Arena,
+    // mode-based execute control, only used to check fetches
+    mbec: bool,
+}
+
+impl EptTable {
+    pub fn new() -> Self {
+        Self {
+            arena: Arena::new(Level::Pml4),
+            mbec: false,
+        }
+    }
+
+    /// Whether the table is used with mode-based execute control, off unless
+    /// set
+    pub fn mode_based_execute(&self) -> bool {
+        self.mbec
+    }
+
+    /// Enable mode-based execute control (the secondary processor-based
+    /// control), where fetches through user mode linear addresses need
+    /// `UserExecute` instead of `Execute`
+    pub fn set_mode_based_execute(&mut self, mbec: bool) {
+        self.mbec = mbec;
+    }
+
+    pub fn translate(&self, gpa: u64, p: Prot) -> Option<u64> {
+        self.translate_detailed(gpa, p).ok()
+    }
+
+    /// Translate an access made through a user mode linear address, where
+    /// fetches need `UserExecute` if mode-based execute control is enabled
+    pub fn translate_user(&self, gpa: u64, p: Prot) -> Option<u64> {
+        self.translate_mode(gpa, p, true).ok()
+    }
+
+    /// Translate a guest physical address, reporting the violation or
+    /// misconfiguration the access would cause
+    pub fn translate_detailed(&self, gpa: u64, p: Prot) -> Result<u64, EptViolation> {
+        self.translate_mode(gpa, p, false)
+    }
+
+    pub(crate) fn translate_mode(
+        &self,
+        gpa: u64,
+        p: Prot,
+        user: bool,
+    ) -> Result<u64, EptViolation> {
+        let mut walk = EptWalk::new(gpa, p, user, self.mbec);
+        let (mut slot, mut level) = (0, Level::Pml4);
+
+        if gpa >> 48 != 0 {
+            return Err(walk.violation(level, 0));
+        }
+
+        loop {
+            let e = self.arena.get(slot, level.index(gpa));
+            walk.entry(level, e)?;
+
+            if is_leaf(level, e) {
+                walk.check()?;
+                return Ok(leaf_addr(level, e) + (gpa & (level.span() - 1)));
+            }
+
+            slot = child(e);
+            level = level.below();
+        }
+    }
+
+    /// Map a 4kb page. A 2mb or 1gb page already mapping `gpa` is split, so
+    /// the rest of it stays mapped.
+    pub fn insert(&mut self, gpa: u64, hpa: u64, f: EptFlags) -> Result<(), Error> {
+        self.check_page(gpa, hpa, 0x1000)?;
+        self.arena.split_to(gpa, Level::Pt, split_bits)?;
+
+        let pt = self
+            .arena
+            .walk_to(gpa, Level::Pt, |old| old.unwrap_or(0) | f.table());
+
+        self.arena.set(pt, pt_index(gpa), hpa | f.bits());
+
+        Ok(())
+    }
+
+    /// Map a 2mb page, terminating the walk at the page directory. A 1gb page
+    /// already mapping `gpa` is split, and smaller pages in the range are an
+    /// overlap.
+    pub fn insert_large(&mut self, gpa: u64, hpa: u64, f: EptFlags) -> Result<(), Error> {
+        self.check_page(gpa, hpa, 0x20_0000)?;
+        self.arena
+            .check_replace(gpa, Level::Pd, |_, e| present(e, self.mbec))?;
+        self.arena.split_to(gpa, Level::Pd, split_bits)?;
+
+        let pd = self
+            .arena
+            .walk_to(gpa, Level::Pd, |old| old.unwrap_or(0) | f.table());
+
+        self.arena
+            .set_leaf(pd, Level::Pd, gpa, hpa | f.bits() | EPT_LARGE);
+
+        Ok(())
+    }
+
+    /// Map a 1gb page, terminating the walk at the page directory pointer
+    /// table. Smaller pages in the range are an overlap.
+    pub fn insert_huge(&mut self, gpa: u64, hpa: u64, f: EptFlags) -> Result<(), Error> {
+        self.check_page(gpa, hpa, 0x4000_0000)?;
+        self.arena
+            .check_replace(gpa, Level::Pdpt, |_, e| present(e, self.mbec))?;
+
+        let pdpt = self
+            .arena
+            .walk_to(gpa, Level::Pdpt, |old| old.unwrap_or(0) | f.table());
+
+        self.arena
+            .set_leaf(pdpt, Level::Pdpt, gpa, hpa | f.bits() | EPT_LARGE);
+
+        Ok(())
+    }
+
+    // check that a page of `size` bytes can be mapped from `hpa` at `gpa`
+    fn check_page(&self, gpa: u64, hpa: u64, size: u64) -> Result<(), Error> {
+        for x in [gpa, hpa].iter() {
+            if x & (size - 1) != 0 {
+                return Err(Error::Misaligned(*x));
+            }
+        }
+
+        check_paddr(gpa, size, GPA_BITS)?;
+        check_paddr(hpa, size, MAX_PHYS_BITS)
+    }
+
+    /// Map `len` bytes of host physical memory at `hpa` to `gpa`, using 1gb and
+    /// 2mb pages wherever the alignment of both addresses allows. Nothing is
+    /// mapped if any part of the range is already mapped.
+    pub fn map_range(&mut self, gpa: u64, hpa: u64, len: u64, f: EptFlags) -> Result<(), Error> {
+        let end = check_range(gpa, hpa, len, MAX_PHYS_BITS)?;
+        check_paddr(gpa, len, GPA_BITS)?;
+
+        let mapped = self.arena.mapped_in(gpa, end, |_, e| present(e, self.mbec));
+
+        if let Some(mapped) = mapped {
+            return Err(Error::Overlap(mapped));
+        }
+
+        for (g, h, level) in arena::pages(gpa, hpa, len) {
+            match level {
+                Level::Pdpt => self.insert_huge(g, h, f)?,
+                Level::Pd => self.insert_large(g, h, f)?,
+                _ => self.insert(g, h, f)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Serialize the tables, returning a write back, 4-level EPTP and the
+    /// table pages. Accessed and dirty flags can be enabled by setting
+    /// `EPTP_ACCESSED_DIRTY` in the EPTP.
+    pub fn commit(self) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        self.commit_at(0)
+    }
+
+    /// Serialize the tables, allocating table pages contiguously from `base`.
+    /// Fails if the table pages would overlap memory mapped by the table.
+    pub fn commit_at(self, base: u64) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        self.check_commit(base)?;
+
+        Ok((eptp(base), self.arena.commit(base)?))
+    }
+
+    /// Serialize the tables into a single host mapping, allocating table
+    /// pages contiguously from `base`. The region's root is the EPTP.
+    pub fn commit_contiguous(&self, base: u64) -> Result<TableRegion, Error> {
+        self.check_commit(base)?;
+
+        Ok(TableRegion::commit(&self.arena, eptp(base), base, false)?)
+    }
+
+    /// Like `commit_contiguous`, with the mapping backed by 2mb huge pages
+    pub fn commit_huge(&self, base: u64) -> Result<TableRegion, Error> {
+        self.check_commit(base)?;
+
+        Ok(TableRegion::commit(&self.arena, eptp(base), base, true)?)
+    }
+
+    fn check_commit(&self, base: u64) -> Result<(), Error> {
+        let end = commit_region(base, self.arena.tables() as u64)?;
+
+        match self.overlap(base, end) {
+            Some(hpa) => Err(Error::TableOverlap(hpa)),
+            None => Ok(()),
+        }
+    }
+
+    fn overlap(&self, start: u64, end: u64) -> Option<u64> {
+        self.arena
+            .leaves()
+            .filter(|&(_, e)| present(e, self.mbec))
+            .map(|(level, e)| (leaf_addr(level, e), level.span()))
+            .find(|&(hpa, size)| overlaps(start, end, hpa, size))
+            .map(|(hpa, _)| hpa)
+    }
+}
+
+impl Default for EptTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,83 @@
+//
+// Errors
+//
+use std::fmt;
+use std::io;
+
+/// Why a change to a table or a commit was rejected
+#[derive(Debug)]
+pub enum Error {
+    /// An address or length isn't aligned to the size of the page
+    Misaligned(u64),
+    /// A virtual address isn't canonical for the paging mode
+    NonCanonical(u64),
+    /// The range overlaps an existing mapping at this virtual address
+    Overlap(u64),
+    /// The table pages would overlap memory mapped by the table at this
+    /// physical address
+    TableOverlap(u64),
+    /// A physical address doesn't fit in the physical address width
+    PhysicalAddress(u64),
+    /// A physical address width is narrower than a page or wider than an
+    /// entry can hold
+    PhysicalAddressWidth(u8),
+    /// The walk to this virtual address goes through a table entry read
+    /// with reserved bits set
+    Reserved(u64),
+    /// The tables need this many pages, more than their region holds
+    Capacity(usize),
+    /// Allocating host memory for the tables failed
+    Alloc(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Misaligned(x) => write!(f, "{:#x} is not page aligned", x),
+            Error::NonCanonical(vaddr) => write!(f, "{:#x} is not canonical", vaddr),
+            Error::Overlap(vaddr) => write!(f, "existing mapping at {:#x}", vaddr),
+            Error::TableOverlap(paddr) => {
+                write!(f, "table pages overlap mapped memory at {:#x}", paddr)
+            }
+            Error::PhysicalAddress(paddr) => {
+                write!(f, "{:#x} exceeds the physical address width", paddr)
+            }
+            Error::PhysicalAddressWidth(bits) => {
+                write!(f, "invalid physical address width {}", bits)
+            }
+            Error::Reserved(vaddr) => {
+                write!(
+                    f,
+                    "{:#x} is mapped through an entry with reserved bits",
+                    vaddr
+                )
+            }
+            Error::Capacity(pages) => write!(f, "{} table pages don't fit", pages),
+            Error::Alloc(e) => write!(f, "allocating tables: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Alloc(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Alloc(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Alloc(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidInput, e),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//
+// Serializing tables
+//
+use serde::de::{self, Deserializer};
+use serde::ser::{self, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::{Flags, MemoryType, PageTable, Paging};
+
+// the flags which are stored in their own fields
+const SEPARATE: Flags = Flags::from_bits_truncate(
+    Flags::WriteThrough.bits()
+        | Flags::CacheDisabled.bits()
+        | Flags::AttributeTable.bits()
+        | Flags::ProtectionKey.bits(),
+);
+
+/// A `PageTable` serializes as its paging mode, physical address width, PAT
+/// and the list of pages it maps, and deserializes into a table mapping the
+/// same pages with the same effective flags
+#[derive(Serialize, Deserialize)]
+struct Layout {
+    paging: Paging,
+    phys_bits: u8,
+    pat: u64,
+    mappings: Vec<Page>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Page {
+    vaddr: u64,
+    paddr: u64,
+    size: u64,
+    /// The bits of `Flags` besides PWT, PCD, PAT and the protection key
+    flags: u64,
+    /// The type selected by PWT, PCD and PAT in the layout's PAT
+    memory_type: MemoryType,
+    pkey: u8,
+}
+
+impl Serialize for PageTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut mappings = vec![];
+
+        for m in self.mappings() {
+            let memory_type = m.flags.memory_type(self.pat).ok_or_else(|| {
+                ser::Error::custom(format!(
+                    "{:#x} selects a reserved memory type in the PAT",
+                    m.vaddr
+                ))
+            })?;
+
+            mappings.push(Page {
+                vaddr: m.vaddr,
+                paddr: m.paddr,
+                size: m.size,
+                flags: (m.flags - SEPARATE).bits(),
+                memory_type,
+                pkey: m.flags.key(),
+            });
+        }
+
+        let layout = Layout {
+            paging: self.paging,
+            phys_bits: self.phys_bits,
+            pat: self.pat,
+            mappings,
+        };
+
+        layout.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PageTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let layout = Layout::deserialize(deserializer)?;
+
+        let mut r = PageTable::with_phys_bits(layout.paging, layout.phys_bits)
+            .map_err(de::Error::custom)?;
+        r.set_pat(layout.pat);
+
+        for x in layout.mappings.iter() {
+            let f = page_flags(x, layout.pat).map_err(de::Error::custom)?;
+
+            let inserted = match x.size {
+                0x1000 => r.insert(x.vaddr, x.paddr, f),
+                0x20_0000 => r.insert_large(x.vaddr, x.paddr, f),
+                0x4000_0000 => r.insert_huge(x.vaddr, x.paddr, f),
+                size => return Err(de::Error::custom(format!("{:#x} is not a page size", size))),
+            };
+
+            inserted.map_err(de::Error::custom)?;
+        }
+
+        Ok(r)
+    }
+}
+
+// the flags to insert a page with, selecting its memory type in `pat`
+fn page_flags(x: &Page, pat: u64) -> Result<Flags, String> {
+    let f = Flags::from_bits(x.flags)
+        .filter(|f| !f.intersects(SEPARATE))
+        .ok_or_else(|| format!("invalid flags {:#x}", x.flags))?;
+
+    if x.pkey > 0xf {
+        return Err(format!("invalid protection key {}", x.pkey));
+    }
+
+    let f = f
+        .with_memory_type(x.memory_type, pat)
+        .ok_or_else(|| format!("{:?} isn't in the PAT", x.memory_type))?;
+
+    Ok(f.with_key(x.pkey))
+}
@@ -0,0 +1,449 @@
+//
+// Legacy (32-bit and PAE) paging
+//
+use std::collections::BTreeMap;
+
+use memmap::MmapMut;
+
+use crate::{
+    check_paddr, commit_next, commit_region, overlaps, table_data, Error, Flags, PdFlags, Prot,
+    PteFlags, LARGE_PAT, MAX_PHYS_BITS,
+};
+
+const fn pd32_index(vaddr: u32) -> usize {
+    (vaddr >> 22) as usize & 0x3ff
+}
+
+const fn pt32_index(vaddr: u32) -> usize {
+    (vaddr >> 12) as usize & 0x3ff
+}
+
+const fn pdpt_pae_index(vaddr: u32) -> usize {
+    (vaddr >> 30) as usize & 0b11
+}
+
+const fn pd_pae_index(vaddr: u32) -> usize {
+    (vaddr >> 21) as usize & 0b1_1111_1111
+}
+
+const fn pt_pae_index(vaddr: u32) -> usize {
+    (vaddr >> 12) as usize & 0b1_1111_1111
+}
+
+// bits 51:12 of a pae entry
+const PAE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+// the widest physical address a 4mb page can map with PSE-36
+const PSE36_BITS: u8 = 40;
+
+fn table_data32(backing: &mut MmapMut) -> &mut [u32] {
+    let data = table_data(backing);
+    let len = data.len() * 2;
+
+    unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u32, len) }
+}
+
+// the bits of a leaf entry for the legacy formats, which lack NX
+fn flags32(f: Flags) -> u32 {
+    PteFlags::from_bits_truncate(f.bits()).bits() as u32
+}
+
+// the bits of a non-leaf entry, which lack dirty in all formats and NX in
+// 32-bit mode. NX on a non-leaf entry would apply to the entire range below
+// it, so it's only ever set on leaves.
+fn table_flags(f: Flags) -> u64 {
+    PdFlags::from_bits_truncate((f.table() - Flags::NX).bits()).bits()
+}
+
+// the bits of a pae leaf, where the protection key bits are reserved
+fn pae_leaf(f: Flags) -> u64 {
+    (f - Flags::ProtectionKey).bits()
+}
+
+// check that a page of `size` bytes can be mapped from a physical address
+// of up to `bits` at `vaddr`
+fn check_page(vaddr: u32, paddr: u64, size: u64, bits: u8) -> Result<(), Error> {
+    for x in [vaddr as u64, paddr].iter() {
+        if x & (size - 1) != 0 {
+            return Err(Error::Misaligned(*x));
+        }
+    }
+
+    check_paddr(paddr, size, bits)
+}
+
+/// 2-level, 32-bit paging with optional 4mb (PSE) pages
+pub struct PageTable32 {
+    // pde flags, and the frame for 4mb pages. frames for page tables are
+    // filled in when the table is committed.
+    pd: Box<[u32; 1024]>,
+    pt: BTreeMap<usize, Box<[u32; 1024]>>,
+}
+
+impl PageTable32 {
+    pub fn translate(&self, vaddr: u32, p: Prot) -> Option<u64> {
+        let pde = self.pd[pd32_index(vaddr)];
+
+        if pde & PdFlags::Present.bits() as u32 == 0 {
+            return None;
+        }
+        if p.contains(Prot::W) && pde & PdFlags::Writable.bits() as u32 == 0 {
+            return None;
+        }
+
+        if pde & PdFlags::Size.bits() as u32 != 0 {
+            // PSE-36 stores bits 39:32 of the frame in bits 20:13
+            let frame = (pde & 0xffc0_0000) as u64 | ((pde as u64 >> 13) & 0xff) << 32;
+
+            return Some(frame + (vaddr & 0x3f_ffff) as u64);
+        }
+
+        let pte = self.pt.get(&pd32_index(vaddr))?[pt32_index(vaddr)];
+
+        if pte & PteFlags::Present.bits() as u32 == 0 {
+            return None;
+        }
+        if p.contains(Prot::W) && pte & PteFlags::Writable.bits() as u32 == 0 {
+            return None;
+        }
+
+        Some((pte & !0xfff) as u64 + (vaddr & 0xfff) as u64)
+    }
+
+    /// Map a 4kb page. A 4mb page already mapping `vaddr` is split, unless
+    /// its frame is above 4gb where a pte can't hold it, which is an overlap.
+    pub fn insert(&mut self, vaddr: u32, paddr: u32, f: Flags) -> Result<(), Error> {
+        check_page(vaddr, paddr as u64, 0x1000, 32)?;
+
+        let pde = self.pd[pd32_index(vaddr)];
+
+        if pde & PdFlags::Size.bits() as u32 != 0 {
+            // PSE-36 frame bits, which a pte has no room for
+            if (pde >> 13) & 0xff != 0 {
+                return Err(Error::Overlap((vaddr & !0x3f_ffff) as u64));
+            }
+
+            self.split(vaddr);
+        }
+
+        self.pd[pd32_index(vaddr)] |= table_flags(f) as u32;
+
+        let pt = self
+            .pt
+            .entry(pd32_index(vaddr))
+            .or_insert_with(|| Box::new([0; 1024]));
+
+        pt[pt32_index(vaddr)] = paddr | flags32(f);
+
+        Ok(())
+    }
+
+    /// Map a 4mb page. Physical addresses up to 40 bits are encoded with
+    /// PSE-36. 4kb pages in the range are an overlap.
+    pub fn insert_large(&mut self, vaddr: u32, paddr: u64, f: Flags) -> Result<(), Error> {
+        check_page(vaddr, paddr, 0x40_0000, PSE36_BITS)?;
+
+        let mapped = self.pt.get(&pd32_index(vaddr)).and_then(|pt| {
+            pt.iter()
+                .position(|&pte| pte & PteFlags::Present.bits() as u32 != 0)
+        });
+
+        if let Some(ii) = mapped {
+            return Err(Error::Overlap((vaddr + ii as u32 * 0x1000) as u64));
+        }
+
+        let frame = (paddr & 0xffc0_0000) as u32 | (((paddr >> 32) & 0xff) << 13) as u32;
+
+        self.pt.remove(&pd32_index(vaddr));
+        let f = PdFlags::from_bits_truncate(f.large()) | PdFlags::Size;
+        self.pd[pd32_index(vaddr)] = frame | f.bits() as u32;
+
+        Ok(())
+    }
+
+    /// Serialize the tables, returning the value for cr3 and the table pages
+    pub fn commit(self) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        self.commit_at(0)
+    }
+
+    /// Serialize the tables, allocating table pages contiguously from `base`.
+    /// All of the tables must be below 4gb.
+    pub fn commit_at(self, base: u64) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        let start = base;
+        let end = commit_region(start, 1 + self.pt.len() as u64)?;
+
+        if end > 1 << 32 {
+            return Err(Error::PhysicalAddress(start));
+        }
+
+        if let Some(paddr) = self.overlap(start, end) {
+            return Err(Error::TableOverlap(paddr));
+        }
+
+        let mut r = BTreeMap::new();
+        let mut base = start;
+
+        let mut pd_backing = MmapMut::map_anon(0x1000)?;
+        let pd_paddr = commit_next(&mut base);
+
+        table_data32(&mut pd_backing).copy_from_slice(&self.pd[..]);
+
+        for (ii, pt) in self.pt.iter() {
+            let mut pt_backing = MmapMut::map_anon(0x1000)?;
+            let pt_paddr = commit_next(&mut base);
+
+            table_data32(&mut pt_backing).copy_from_slice(&pt[..]);
+
+            table_data32(&mut pd_backing)[*ii] |= pt_paddr as u32;
+            r.insert(pt_paddr, pt_backing);
+        }
+
+        r.insert(pd_paddr, pd_backing);
+
+        Ok((pd_paddr, r))
+    }
+
+    fn overlap(&self, start: u64, end: u64) -> Option<u64> {
+        let large = self
+            .pd
+            .iter()
+            .filter(|&&pde| pde & PdFlags::Size.bits() as u32 != 0)
+            .map(|&pde| (pde & 0xffc0_0000) as u64 | ((pde as u64 >> 13) & 0xff) << 32)
+            .find(|&paddr| overlaps(start, end, paddr, 0x40_0000));
+
+        large.or_else(|| {
+            self.pt
+                .values()
+                .flat_map(|pt| pt.iter())
+                .filter(|&&pte| pte & PteFlags::Present.bits() as u32 != 0)
+                .map(|&pte| (pte & !0xfff) as u64)
+                .find(|&paddr| overlaps(start, end, paddr, 0x1000))
+        })
+    }
+
+    // replace the 4mb page mapping vaddr with a page table mapping the same
+    // range with 4kb pages. The frame must be below 4gb.
+    fn split(&mut self, vaddr: u32) {
+        let pde = self.pd[pd32_index(vaddr)];
+
+        let frame = pde & 0xffc0_0000;
+        let f = Flags::from_large((pde & 0x1fff) as u64);
+
+        self.pd[pd32_index(vaddr)] = table_flags(f) as u32;
+
+        let mut pt = Box::new([0; 1024]);
+        for (ii, pte) in pt.iter_mut().enumerate() {
+            *pte = (frame + ii as u32 * 0x1000) | flags32(f);
+        }
+
+        self.pt.insert(pd32_index(vaddr), pt);
+    }
+}
+
+impl Default for PageTable32 {
+    fn default() -> Self {
+        Self {
+            pd: Box::new([0; 1024]),
+            pt: BTreeMap::new(),
+        }
+    }
+}
+
+/// 3-level PAE paging with optional 2mb pages
+#[derive(Default)]
+pub struct PageTablePae {
+    // as with PageTable32, non-leaf entries only hold flags until commit
+    pd: BTreeMap<usize, Box<[u64; 512]>>,
+    // keyed by the combined pdpt and pd index
+    pt: BTreeMap<usize, Box<[u64; 512]>>,
+}
+
+impl PageTablePae {
+    pub fn translate(&self, vaddr: u32, p: Prot) -> Option<u64> {
+        // pdptes only have a present bit, so there's nothing else to check
+        let pd = self.pd.get(&pdpt_pae_index(vaddr))?;
+
+        let pde = PdFlags::from_bits_truncate(pd[pd_pae_index(vaddr)]);
+
+        if !pde.contains(PdFlags::Present) {
+            return None;
+        }
+        if p.contains(Prot::W) && !pde.contains(PdFlags::Writable) {
+            return None;
+        }
+        if p.contains(Prot::X) && pde.contains(PdFlags::NX) {
+            return None;
+        }
+
+        if pde.contains(PdFlags::Size) {
+            let frame = pd[pd_pae_index(vaddr)] & PAE_ADDR_MASK & !0x1f_ffff;
+
+            return Some(frame + (vaddr & 0x1f_ffff) as u64);
+        }
+
+        let raw = self.pt.get(&Self::pt_key(vaddr))?[pt_pae_index(vaddr)];
+        let pte = PteFlags::from_bits_truncate(raw);
+
+        if !pte.contains(PteFlags::Present) {
+            return None;
+        }
+        if p.contains(Prot::W) && !pte.contains(PteFlags::Writable) {
+            return None;
+        }
+        if p.contains(Prot::X) && pte.contains(PteFlags::NX) {
+            return None;
+        }
+
+        Some((raw & PAE_ADDR_MASK) + (vaddr & 0xfff) as u64)
+    }
+
+    /// Map a 4kb page. A 2mb page already mapping `vaddr` is split, so the
+    /// rest of it stays mapped.
+    pub fn insert(&mut self, vaddr: u32, paddr: u64, f: Flags) -> Result<(), Error> {
+        check_page(vaddr, paddr, 0x1000, MAX_PHYS_BITS)?;
+
+        if self.pd_mut(vaddr)[pd_pae_index(vaddr)] & PdFlags::Size.bits() != 0 {
+            self.split(vaddr);
+        }
+
+        self.pd_mut(vaddr)[pd_pae_index(vaddr)] |= table_flags(f);
+
+        let pt = self
+            .pt
+            .entry(Self::pt_key(vaddr))
+            .or_insert_with(|| Box::new([0; 512]));
+
+        pt[pt_pae_index(vaddr)] = paddr | PteFlags::from_bits_truncate(pae_leaf(f)).bits();
+
+        Ok(())
+    }
+
+    /// Map a 2mb page, terminating the walk at the page directory. 4kb pages
+    /// in the range are an overlap.
+    pub fn insert_large(&mut self, vaddr: u32, paddr: u64, f: Flags) -> Result<(), Error> {
+        check_page(vaddr, paddr, 0x20_0000, MAX_PHYS_BITS)?;
+
+        let mapped = self.pt.get(&Self::pt_key(vaddr)).and_then(|pt| {
+            pt.iter()
+                .position(|&pte| pte & PteFlags::Present.bits() != 0)
+        });
+
+        if let Some(ii) = mapped {
+            return Err(Error::Overlap((vaddr + ii as u32 * 0x1000) as u64));
+        }
+
+        self.pt.remove(&Self::pt_key(vaddr));
+
+        let pd = self.pd_mut(vaddr);
+
+        pd[pd_pae_index(vaddr)] = paddr
+            | PdFlags::from_bits_truncate((f - Flags::ProtectionKey).large()).bits()
+            | PdFlags::Size.bits();
+
+        Ok(())
+    }
+
+    /// Serialize the tables, returning the value for cr3 and the table pages.
+    /// The pdpt is only 32 bytes, but still gets a page to itself.
+    pub fn commit(self) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        self.commit_at(0)
+    }
+
+    /// Serialize the tables, allocating table pages contiguously from `base`.
+    /// The pdpt must be below 4gb, as cr3 is only 32 bits with PAE.
+    pub fn commit_at(self, base: u64) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        let start = base;
+        let end = commit_region(start, 1 + (self.pd.len() + self.pt.len()) as u64)?;
+
+        if start >= 1 << 32 {
+            return Err(Error::PhysicalAddress(start));
+        }
+
+        if let Some(paddr) = self.overlap(start, end) {
+            return Err(Error::TableOverlap(paddr));
+        }
+
+        let mut r = BTreeMap::new();
+        let mut base = start;
+
+        let mut pdpt_backing = MmapMut::map_anon(0x1000)?;
+        let pdpt_paddr = commit_next(&mut base);
+
+        for (ii, pd) in self.pd.iter() {
+            let mut pd_backing = MmapMut::map_anon(0x1000)?;
+            let pd_paddr = commit_next(&mut base);
+
+            table_data(&mut pd_backing).copy_from_slice(&pd[..]);
+
+            for (jj, pt) in self.pt.range(ii * 512..(ii + 1) * 512) {
+                let mut pt_backing = MmapMut::map_anon(0x1000)?;
+                let pt_paddr = commit_next(&mut base);
+
+                table_data(&mut pt_backing).copy_from_slice(&pt[..]);
+
+                table_data(&mut pd_backing)[jj % 512] |= pt_paddr;
+                r.insert(pt_paddr, pt_backing);
+            }
+
+            // everything but present is reserved in a pdpte
+            table_data(&mut pdpt_backing)[*ii] = pd_paddr | PdFlags::Present.bits();
+            r.insert(pd_paddr, pd_backing);
+        }
+
+        r.insert(pdpt_paddr, pdpt_backing);
+
+        Ok((pdpt_paddr, r))
+    }
+
+    fn overlap(&self, start: u64, end: u64) -> Option<u64> {
+        let large = self
+            .pd
+            .values()
+            .flat_map(|pd| pd.iter())
+            .filter(|&&pde| pde & PdFlags::Size.bits() != 0)
+            .map(|&pde| pde & PAE_ADDR_MASK & !0x1f_ffff)
+            .find(|&paddr| overlaps(start, end, paddr, 0x20_0000));
+
+        large.or_else(|| {
+            self.pt
+                .values()
+                .flat_map(|pt| pt.iter())
+                .filter(|&&pte| pte & PteFlags::Present.bits() != 0)
+                .map(|&pte| pte & PAE_ADDR_MASK)
+                .find(|&paddr| overlaps(start, end, paddr, 0x1000))
+        })
+    }
+
+    // replace the 2mb page mapping vaddr with a page table mapping the same
+    // range with 4kb pages. Unlike a 32-bit pte, a pae pte holds any frame
+    // the 2mb page can.
+    fn split(&mut self, vaddr: u32) {
+        let pd = self.pd_mut(vaddr);
+        let pde = pd[pd_pae_index(vaddr)];
+
+        let frame = pde & PAE_ADDR_MASK & !0x1f_ffff;
+        let f = pde & (!PAE_ADDR_MASK | LARGE_PAT);
+
+        let mut leaf = PteFlags::from_bits_truncate(f & !PdFlags::Size.bits());
+        leaf.set(PteFlags::AttributeTable, f & LARGE_PAT != 0);
+
+        pd[pd_pae_index(vaddr)] = table_flags(Flags::from_bits_truncate(f));
+
+        let mut pt = Box::new([0; 512]);
+        for (ii, pte) in pt.iter_mut().enumerate() {
+            *pte = (frame + ii as u64 * 0x1000) | leaf.bits();
+        }
+
+        self.pt.insert(Self::pt_key(vaddr), pt);
+    }
+
+    fn pt_key(vaddr: u32) -> usize {
+        pdpt_pae_index(vaddr) * 512 + pd_pae_index(vaddr)
+    }
+
+    fn pd_mut(&mut self, vaddr: u32) -> &mut [u64; 512] {
+        self.pd
+            .entry(pdpt_pae_index(vaddr))
+            .or_insert_with(|| Box::new([0; 512]))
+    }
+}
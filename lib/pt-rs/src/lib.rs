@@ -1,15 +1,44 @@
 #[macro_use]
 extern crate bitflags;
 
-use std::boxed::Box;
 use std::collections::BTreeMap;
-use std::io::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::iter::Peekable;
 use std::mem;
-use std::ops::Index;
 use std::slice;
 
 use memmap::MmapMut;
 
+use arena::{child, leaf_addr, table_entry, Arena};
+
+mod arena;
+mod diff;
+mod dump;
+mod ept;
+mod error;
+#[cfg(feature = "serde")]
+mod layout;
+mod legacy;
+mod live;
+mod nested;
+mod pat;
+mod region;
+mod tlb;
+mod virt;
+
+pub use diff::Diff;
+pub use dump::{Dump, Explain};
+pub use ept::{EptFlags, EptTable, EptViolation, EPTP_ACCESSED_DIRTY};
+pub use error::Error;
+pub use legacy::{PageTable32, PageTablePae};
+pub use live::LiveTable;
+pub use nested::NestedFault;
+pub use pat::{MemoryType, PAT_DEFAULT};
+pub use region::TableRegion;
+pub use tlb::Tlb;
+pub use virt::{AccessFault, PhysMem};
+
 bitflags! {
     pub struct Prot : u32 {
         const R = 1 << 2;
@@ -18,6 +47,9 @@ bitflags! {
     }
 }
 
+const fn pml5_index(vaddr: u64) -> usize {
+    vaddr as usize >> (12 + (9 * 4)) & 0b1_1111_1111
+}
 
 const fn pml4_index(vaddr: u64) -> usize {
     vaddr as usize >> (12 + (9 * 3)) & 0b1_1111_1111
@@ -35,8 +67,26 @@ const fn pt_index(vaddr: u64) -> usize {
     vaddr as usize >> (12 + (9 * 0)) & 0b1_1111_1111
 }
 
-const fn page_offset(vaddr: u64) -> usize {
-    vaddr as usize & 0xfff
+// check that a range can be mapped with pages from physical addresses of up
+// to `bits`, returning its end
+fn check_range(vaddr: u64, paddr: u64, len: u64, bits: u8) -> Result<u64, Error> {
+    for x in [vaddr, paddr, len].iter() {
+        if x & 0xfff != 0 {
+            return Err(Error::Misaligned(*x));
+        }
+    }
+
+    check_paddr(paddr, len, bits)?;
+
+    Ok(vaddr.saturating_add(len))
+}
+
+// check that `len` bytes from `paddr` fit in a physical address width
+fn check_paddr(paddr: u64, len: u64, bits: u8) -> Result<(), Error> {
+    match paddr.checked_add(len) {
+        Some(end) if end <= 1 << bits => Ok(()),
+        _ => Err(Error::PhysicalAddress(paddr)),
+    }
 }
 
 fn commit_next(base: &mut u64) -> u64 {
@@ -45,8 +95,85 @@ fn commit_next(base: &mut u64) -> u64 {
     r
 }
 
+const fn overlaps(start: u64, end: u64, paddr: u64, size: u64) -> bool {
+    paddr < end && start < paddr + size
+}
+
+// validate a caller supplied table base, and find the end of the region the
+// tables will be allocated in
+fn commit_region(base: u64, tables: u64) -> Result<u64, Error> {
+    if base & 0xfff != 0 {
+        return Err(Error::Misaligned(base));
+    }
+
+    check_paddr(base, tables * 0x1000, MAX_PHYS_BITS)?;
+
+    Ok(base + tables * 0x1000)
+}
+
+// bits 51:12 of an entry
+const PADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+// the widest physical address an entry can hold
+const MAX_PHYS_BITS: u8 = 52;
+
+// check that a physical address width covers at least a page, and fits in
+// an entry
+fn check_phys_bits(bits: u8) -> Result<(), Error> {
+    match (12..=MAX_PHYS_BITS).contains(&bits) {
+        true => Ok(()),
+        false => Err(Error::PhysicalAddressWidth(bits)),
+    }
+}
+
+// bit 52 is ignored by the cpu in every entry. `from_physical` sets it on
+// table entries which had reserved bits set in place of their address, as
+// the arena keeps them aside.
+const RESERVED: u64 = 1 << 52;
+
+// read a table page out of guest physical memory
+fn read_table<F>(read: &mut F, paddr: u64) -> Result<[u64; 512], io::Error>
+where
+    F: FnMut(u64, &mut [u8]) -> Result<(), io::Error>,
+{
+    let mut buf = [0u8; 0x1000];
+    read(paddr & PADDR_MASK, &mut buf)?;
+
+    let mut r = [0u64; 512];
+    for (e, b) in r.iter_mut().zip(buf.chunks(8)) {
+        *e = u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+    }
+
+    Ok(r)
+}
+
+// read table pages out of the map returned by `commit`
+fn committed_reader(
+    tables: &BTreeMap<u64, MmapMut>,
+) -> impl FnMut(u64, &mut [u8]) -> Result<(), io::Error> + '_ {
+    move |gpa, buf| match tables.get(&gpa) {
+        Some(page) => {
+            buf.copy_from_slice(&page[..buf.len()]);
+            Ok(())
+        }
+        None => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("no table committed at {:#x}", gpa),
+        )),
+    }
+}
+
+fn table_data(backing: &mut MmapMut) -> &mut [u64] {
+    unsafe {
+        slice::from_raw_parts_mut(
+            backing.as_mut_ptr() as *mut _,
+            0x1000 / mem::size_of::<u64>(),
+        )
+    }
+}
+
 //
-// PTE
+// Entries
 //
 bitflags! {
     pub struct PteFlags : u64 {
@@ -59,584 +186,1268 @@ bitflags! {
         const Dirty = 1 << 6;
         const AttributeTable = 1 << 7;
         const Global = 1 << 8;
+        const ProtectionKey = 0xf << 59;
         const NX = 1 << 63;
     }
 }
 
-pub struct Pte {
-    paddr: u64,
-    flags: PteFlags,
-}
-
-impl Pte {
-    fn flags(&self) -> PteFlags {
-        self.flags
-    }
-
-    fn set_flags(&mut self, f: PteFlags) {
-        self.flags.remove(PteFlags::all());
-        self.flags.insert(f)
+bitflags! {
+    pub struct PtFlags : u64 {
+        const Present = 1 << 0;
+        const Writable = 1 << 1;
+        const User = 1 << 2;
+        const WriteThrough = 1 << 3;
+        const CacheDisabled = 1 << 4;
+        const Accessed = 1 << 5;
+        // only valid when Size is set
+        const Dirty = 1 << 6;
+        const Size = 1 << 7;
+        // only valid when Size is set
+        const Global = 1 << 8;
+        // only valid when Size is set, where bit 7 is taken by Size
+        const AttributeTable = 1 << 12;
+        // only valid when Size is set
+        const ProtectionKey = 0xf << 59;
+        const NX = 1 << 63;
     }
+}
 
-    fn paddr(&self) -> u64 {
-        self.paddr
+bitflags! {
+    pub struct PdFlags : u64 {
+        const Present = 1 << 0;
+        const Writable = 1 << 1;
+        const User = 1 << 2;
+        const WriteThrough = 1 << 3;
+        const CacheDisabled = 1 << 4;
+        const Accessed = 1 << 5;
+        // only valid when Size is set
+        const Dirty = 1 << 6;
+        const Size = 1 << 7;
+        // only valid when Size is set
+        const Global = 1 << 8;
+        // only valid when Size is set, where bit 7 is taken by Size
+        const AttributeTable = 1 << 12;
+        // only valid when Size is set
+        const ProtectionKey = 0xf << 59;
+        const NX = 1 << 63;
     }
+}
 
-    fn set_paddr(&mut self, paddr: u64) {
-        self.paddr = paddr & !0xfff;
+bitflags! {
+    pub struct PdptFlags : u64 {
+        const Present = 1 << 0;
+        const Writable = 1 << 1;
+        const User = 1 << 2;
+        const WriteThrough = 1 << 3;
+        const CacheDisabled = 1 << 4;
+        const Accessed = 1 << 5;
+        const Size = 1 << 7;
+        const NX = 1 << 63;
     }
 }
 
-impl Default for Pte {
-    fn default() -> Self {
-        Self {
-            paddr: 0,
-            flags: PteFlags::empty(),
-        }
+bitflags! {
+    pub struct Pml4Flags : u64 {
+        const Present = 1 << 0;
+        const Writable = 1 << 1;
+        const User = 1 << 2;
+        const WriteThrough = 1 << 3;
+        const CacheDisabled = 1 << 4;
+        const Accessed = 1 << 5;
+        const NX = 1 << 63;
     }
 }
 
 //
-// Pt
+// Page Table
 //
-struct IterPt<'a> {
-    inner: &'a Pt,
-    pos: usize,
-}
-
-impl<'a> Iterator for IterPt<'a> {
-    type Item = Option<&'a Box<Pte>>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.inner.pte.len() {
-            None
-        } else {
-            self.pos += 1;
-            Some(self.inner.pte[self.pos - 1].as_ref())
-        }
-    }
-}
+// the PAT bit of 2mb and 1gb pages
+const LARGE_PAT: u64 = 1 << 12;
 
 bitflags! {
-    pub struct PtFlags : u64 {
+    pub struct Flags : u64 {
         const Present = 1 << 0;
         const Writable = 1 << 1;
         const User = 1 << 2;
         const WriteThrough = 1 << 3;
         const CacheDisabled = 1 << 4;
         const Accessed = 1 << 5;
-        const Size = 1 << 7;
+        const Dirty = 1 << 6;
+        /// Selects the memory type along with PWT and PCD, see
+        /// `with_memory_type`. Stored in bit 12 of 2mb and 1gb pages.
+        const AttributeTable = 1 << 7;
+        /// Kept in the TLB across CR3 writes, see `Tlb::flush`
+        const Global = 1 << 8;
+        /// The protection key of a leaf, see `with_key`
+        const ProtectionKey = 0xf << 59;
         const NX = 1 << 63;
     }
 }
 
-pub struct Pt {
-    pte: [Option<Box<Pte>>; 512],
-    flags: PtFlags,
-}
-
-impl Pt {
-    fn flags(&self) -> PtFlags {
-        self.flags
+impl Flags {
+    /// Assign protection key `key` to the pages mapped with these flags. Only
+    /// leaf entries carry a key, it's never propagated to the tables above.
+    pub fn with_key(self, key: u8) -> Flags {
+        (self - Flags::ProtectionKey) | Flags::from_bits_truncate((key as u64 & 0xf) << 59)
     }
 
-    fn set_flags(&mut self, f: PtFlags) {
-        self.flags.remove(PtFlags::all());
-        self.flags.insert(f)
+    pub fn key(self) -> u8 {
+        ((self & Flags::ProtectionKey).bits() >> 59) as u8
     }
 
-    fn set_pte(&mut self, vaddr: u64, v: Pte) -> &mut Pte {
-        self.pte[pt_index(vaddr)] = Some(Box::new(v));
-        self.pte[pt_index(vaddr)].as_mut().unwrap()
+    // the flags of a leaf which are meaningful to the tables above it. The
+    // cache bits of a table entry select the memory type of the table it
+    // points at, not of the pages below it.
+    fn table(self) -> Flags {
+        self - Flags::WriteThrough
+            - Flags::CacheDisabled
+            - Flags::Dirty
+            - Flags::AttributeTable
+            - Flags::Global
+            - Flags::ProtectionKey
     }
 
-    fn pte(&self, vaddr: u64) -> Option<&Box<Pte>> {
-        self.pte[pt_index(vaddr)].as_ref()
+    // the bits of a 2mb or 1gb leaf, where the PAT bit moves up to make room
+    // for the page size bit
+    fn large(self) -> u64 {
+        let pat = match self.contains(Flags::AttributeTable) {
+            true => LARGE_PAT,
+            false => 0,
+        };
+
+        (self - Flags::AttributeTable).bits() | pat
     }
 
-    fn pte_mut(&mut self, vaddr: u64) -> Option<&mut Box<Pte>> {
-        self.pte[pt_index(vaddr)].as_mut()
+    fn from_large(bits: u64) -> Flags {
+        let mut f = Flags::from_bits_truncate(bits);
+        f.set(Flags::AttributeTable, bits & LARGE_PAT != 0);
+        f
     }
 
-    fn iter(&self) -> IterPt {
-        IterPt {
-            inner: self,
-            pos: 0,
-        }
+    // the permissions a table entry needs to cover both self and other, which
+    // is the least restrictive combination of the two
+    fn combine(self, other: Flags) -> Flags {
+        ((self | other).table() - Flags::NX) | (self & other & Flags::NX)
     }
 }
 
-impl Default for Pt {
-    fn default() -> Self {
-        let pte: [Option<Box<Pte>>; 512] = unsafe { std::mem::zeroed() };
+// the flags for an entry pointing at a table with the given entries
+fn combined<I: IntoIterator<Item = Flags>>(entries: I) -> Flags {
+    entries
+        .into_iter()
+        .fold(None, |acc: Option<Flags>, f| match acc {
+            None => Some(f.table()),
+            Some(acc) => Some(acc.combine(f)),
+        })
+        .unwrap_or_else(Flags::empty)
+}
 
-        Self {
-            pte,
-            flags: PtFlags::empty(),
-        }
-    }
+// the existing flags of a table entry combined with a new mapping below it
+fn merge(bits: u64, f: Flags) -> u64 {
+    Flags::from_bits_truncate(bits).combine(f).bits()
 }
 
-impl Index<usize> for Pt {
-    type Output = Option<Box<Pte>>;
+// the bits of the entries of a table splitting the 2mb or 1gb page `e`, and
+// of the entry pointing at it
+fn split_bits(level: Level, e: u64) -> (u64, u64) {
+    let f = e & (!PADDR_MASK | LARGE_PAT);
 
-    fn index(&self, idx: usize) -> &Option<Box<Pte>> {
-        &self.pte[idx]
-    }
+    let leaf = match level.below() {
+        Level::Pt => {
+            let mut pte = PteFlags::from_bits_truncate(f & !PtFlags::Size.bits());
+            pte.set(PteFlags::AttributeTable, f & LARGE_PAT != 0);
+            pte.bits()
+        }
+        _ => f,
+    };
+
+    (leaf, Flags::from_bits_truncate(f).table().bits())
 }
 
-//
-// Pd
-//
-struct IterPd<'a> {
-    inner: &'a Pd,
-    pos: usize,
+// the flags of a table entry on the walk to a new mapping, from its existing
+// flags if the table was already there
+fn table_flags(old: Option<u64>, f: Flags) -> u64 {
+    match old {
+        None => f.table().bits(),
+        Some(bits) => merge(bits, f),
+    }
 }
 
-impl<'a> Iterator for IterPd<'a> {
-    type Item = Option<&'a Box<Pt>>;
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Paging {
+    /// 4-level paging with 48-bit linear addresses
+    #[default]
+    Level4,
+    /// 5-level paging (CR4.LA57) with 57-bit linear addresses
+    Level5,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.inner.pt.len() {
-            None
-        } else {
-            self.pos += 1;
-            Some(self.inner.pt[self.pos - 1].as_ref())
+impl Paging {
+    fn bits(self) -> u32 {
+        match self {
+            Paging::Level4 => 48,
+            Paging::Level5 => 57,
         }
     }
-}
 
-bitflags! {
-    pub struct PdFlags : u64 {
-        const Present = 1 << 0;
-        const Writable = 1 << 1;
-        const User = 1 << 2;
-        const WriteThrough = 1 << 3;
-        const CacheDisabled = 1 << 4;
-        const Accessed = 1 << 5;
-        const Size = 1 << 7;
-        const NX = 1 << 63;
+    fn is_canonical(self, vaddr: u64) -> bool {
+        let top = (vaddr as i64) >> (self.bits() - 1);
+
+        top == 0 || top == -1
+    }
+
+    // sign extend a position in the linear address space
+    fn canonical(self, pos: u64) -> u64 {
+        let shift = 64 - self.bits();
+
+        ((pos << shift) as i64 >> shift) as u64
     }
 }
 
-pub struct Pd {
-    pt: [Option<Box<Pt>>; 512],
-    flags: PdFlags,
+/// An entry in one of the paging structures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    level: Level,
+    bits: u64,
 }
 
-impl Pd {
-    fn flags(&self) -> PdFlags {
-        self.flags
+impl Entry {
+    /// The table holding the entry
+    pub fn level(&self) -> Level {
+        self.level
     }
 
-    fn set_flags(&mut self, f: PdFlags) {
-        self.flags.remove(PdFlags::all());
-        self.flags.insert(f)
+    /// Whether the entry maps memory, rather than pointing at another table
+    pub fn is_leaf(&self) -> bool {
+        arena::is_leaf(self.level, self.bits)
     }
 
-    fn set_pt(&mut self, vaddr: u64, v: Pt) -> &mut Pt {
-        self.pt[pd_index(vaddr)] = Some(Box::new(v));
-        self.pt[pd_index(vaddr)].as_mut().unwrap()
+    pub fn flags(&self) -> Flags {
+        match self.level {
+            Level::Pdpt | Level::Pd if self.is_leaf() => Flags::from_large(self.bits),
+            _ => Flags::from_bits_truncate(self.bits),
+        }
     }
 
-    fn pt(&self, vaddr: u64) -> Option<&Box<Pt>> {
-        self.pt[pd_index(vaddr)].as_ref()
+    /// The physical address mapped by a leaf. Tables don't have one until
+    /// they're committed.
+    pub fn paddr(&self) -> Option<u64> {
+        match self.is_leaf() {
+            true => Some(leaf_addr(self.level, self.bits)),
+            false => None,
+        }
     }
+}
 
-    fn pt_mut(&mut self, vaddr: u64) -> Option<&mut Box<Pt>> {
-        self.pt[pd_index(vaddr)].as_mut()
+// the bits of an entry read from memory which are kept, by the type of the
+// structure it points at
+fn known_bits(level: Level, e: u64) -> u64 {
+    match level {
+        Level::Pml5 => Pml4Flags::from_bits_truncate(e).bits(),
+        Level::Pml4 => PdptFlags::from_bits_truncate(e).bits(),
+        Level::Pdpt => PdFlags::from_bits_truncate(e).bits(),
+        Level::Pd => PtFlags::from_bits_truncate(e).bits(),
+        Level::Pt => PteFlags::from_bits_truncate(e).bits(),
     }
+}
 
-    fn iter(&self) -> IterPd {
-        IterPd {
-            inner: self,
-            pos: 0,
+// the reserved bits set in a present entry as it's laid out in memory:
+// address bits beyond the physical address width, the page size bit in the
+// pml5 and pml4, and the address bits below the frame of large pages
+fn reserved_bits(level: Level, e: u64, phys_bits: u8) -> u64 {
+    let addr = e & PADDR_MASK & !((1 << phys_bits) - 1);
+
+    let r = match level {
+        Level::Pml5 | Level::Pml4 => e & PdFlags::Size.bits(),
+        Level::Pdpt | Level::Pd if arena::is_leaf(level, e) => {
+            e & (level.span() - 1) & !(LARGE_PAT | 0xfff)
         }
-    }
+        _ => 0,
+    };
+
+    addr | r
 }
 
-impl Default for Pd {
-    fn default() -> Self {
-        let pt: [Option<Box<Pt>>; 512] = unsafe { std::mem::zeroed() };
+#[derive(Clone)]
+pub struct PageTable {
+    paging: Paging,
+    // MAXPHYADDR, the width of the physical addresses in entries
+    phys_bits: u8,
+    // IA32_PAT, only used to name the memory types of the mappings
+    pat: u64,
+    // the root is the pml4 with 4-level paging and the pml5 with 5-level
+    arena: Arena,
+}
+
+impl PageTable {
+    pub fn new(paging: Paging) -> Self {
+        let root = match paging {
+            Paging::Level4 => Level::Pml4,
+            Paging::Level5 => Level::Pml5,
+        };
 
         Self {
-            pt,
-            flags: PdFlags::empty(),
+            paging,
+            phys_bits: MAX_PHYS_BITS,
+            pat: PAT_DEFAULT,
+            arena: Arena::new(root),
         }
     }
-}
 
-impl Index<usize> for Pd {
-    type Output = Option<Box<Pt>>;
+    /// A table for a cpu with `phys_bits` of physical address (CPUID
+    /// 0x80000008 EAX[7:0]). Mapping memory beyond it is rejected, and the
+    /// bits above it are reserved in entries read from memory. Fails if it's
+    /// wider than the 52 bits an entry holds.
+    pub fn with_phys_bits(paging: Paging, phys_bits: u8) -> Result<Self, Error> {
+        check_phys_bits(phys_bits)?;
+
+        Ok(Self {
+            phys_bits,
+            ..Self::new(paging)
+        })
+    }
 
-    fn index(&self, idx: usize) -> &Option<Box<Pt>> {
-        &self.pt[idx]
+    /// Reconstruct a 4-level table from guest physical memory. `read` is
+    /// called to fill a buffer from a guest physical address, and is only
+    /// ever used to read whole table pages.
+    pub fn from_physical<F>(cr3: u64, read: F) -> Result<Self, io::Error>
+    where
+        F: FnMut(u64, &mut [u8]) -> Result<(), io::Error>,
+    {
+        Self::from_physical_with(Paging::Level4, MAX_PHYS_BITS, cr3, read)
     }
-}
 
-//
-// PDPT
-//
-struct IterPdpt<'a> {
-    inner: &'a Pdpt,
-    pos: usize,
-}
+    /// Reconstruct a 5-level table from guest physical memory
+    pub fn from_physical_la57<F>(cr3: u64, read: F) -> Result<Self, io::Error>
+    where
+        F: FnMut(u64, &mut [u8]) -> Result<(), io::Error>,
+    {
+        Self::from_physical_with(Paging::Level5, MAX_PHYS_BITS, cr3, read)
+    }
 
-impl<'a> Iterator for IterPdpt<'a> {
-    type Item = Option<&'a Box<Pd>>;
+    /// Reconstruct a table from the pages returned by `commit`, to read back
+    /// the accessed and dirty bits the guest set while running on it
+    pub fn from_committed(
+        paging: Paging,
+        cr3: u64,
+        tables: &BTreeMap<u64, MmapMut>,
+    ) -> Result<Self, io::Error> {
+        Self::from_physical_with(paging, MAX_PHYS_BITS, cr3, committed_reader(tables))
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.inner.pd.len() {
-            None
-        } else {
-            self.pos += 1;
-            Some(self.inner.pd[self.pos - 1].as_ref())
+    /// Reconstruct a table from guest physical memory for a cpu with
+    /// `phys_bits` of physical address. Entries with reserved bits set are
+    /// kept, and fault with `PfCode::Reserved` when translating through
+    /// them. The tables below such an entry aren't read, as the cpu never
+    /// reaches them. An invalid `phys_bits` fails with `InvalidInput`.
+    pub fn from_physical_with<F>(
+        paging: Paging,
+        phys_bits: u8,
+        cr3: u64,
+        mut read: F,
+    ) -> Result<Self, io::Error>
+    where
+        F: FnMut(u64, &mut [u8]) -> Result<(), io::Error>,
+    {
+        let mut r = Self::with_phys_bits(paging, phys_bits)?;
+        let root = read_table(&mut read, cr3)?;
+        let level = r.arena.root();
+
+        r.read_tables(0, level, &root, &mut read)?;
+
+        Ok(r)
+    }
+
+    // copy the present entries of a table read from memory into `slot`,
+    // reading the tables they point at in turn
+    fn read_tables<F>(
+        &mut self,
+        slot: usize,
+        level: Level,
+        entries: &[u64; 512],
+        read: &mut F,
+    ) -> Result<(), io::Error>
+    where
+        F: FnMut(u64, &mut [u8]) -> Result<(), io::Error>,
+    {
+        for (ii, &e) in entries.iter().enumerate() {
+            if e & Flags::Present.bits() == 0 {
+                continue;
+            }
+
+            // leaves keep their whole address, including any reserved bits
+            if arena::is_leaf(level, e) {
+                self.arena
+                    .set(slot, ii, (e & PADDR_MASK) | known_bits(level, e));
+                continue;
+            }
+
+            // bit 12 only holds the PAT bit in large pages
+            let f = known_bits(level, e) & !LARGE_PAT;
+
+            if reserved_bits(level, e, self.phys_bits) != 0 {
+                self.arena.set_reserved(slot, ii, e, f);
+                continue;
+            }
+
+            let table = self.arena.alloc(level.below());
+            self.read_tables(table, level.below(), &read_table(read, e)?, read)?;
+            self.arena.set(slot, ii, table_entry(table) | f);
         }
+
+        Ok(())
     }
-}
 
-bitflags! {
-    pub struct PdptFlags : u64 {
-        const Present = 1 << 0;
-        const Writable = 1 << 1;
-        const User = 1 << 2;
-        const WriteThrough = 1 << 3;
-        const CacheDisabled = 1 << 4;
-        const Accessed = 1 << 5;
-        const Size = 1 << 7;
-        const NX = 1 << 63;
+    pub fn paging(&self) -> Paging {
+        self.paging
     }
-}
 
-pub struct Pdpt {
-    pd: [Option<Box<Pd>>; 512],
-    flags: PdptFlags,
-}
+    pub fn phys_bits(&self) -> u8 {
+        self.phys_bits
+    }
 
-impl Pdpt {
-    fn flags(&self) -> PdptFlags {
-        self.flags
+    /// The value of IA32_PAT the table is used with, `PAT_DEFAULT` unless
+    /// set. Serializing records the memory type each mapping selects in it.
+    pub fn pat(&self) -> u64 {
+        self.pat
     }
 
-    fn set_flags(&mut self, f: PdptFlags) {
-        self.flags.remove(PdptFlags::all());
-        self.flags.insert(f)
+    pub fn set_pat(&mut self, pat: u64) {
+        self.pat = pat;
     }
 
-    fn set_pd(&mut self, vaddr: u64, v: Pd) -> &mut Pd {
-        self.pd[pdpt_index(vaddr)] = Some(Box::new(v));
-        self.pd[pdpt_index(vaddr)].as_mut().unwrap()
+    /// Check if a virtual address is canonical for the current paging mode
+    pub fn is_canonical(&self, vaddr: u64) -> bool {
+        self.paging.is_canonical(vaddr)
     }
 
-    fn pd(&self, vaddr: u64) -> Option<&Box<Pd>> {
-        self.pd[pdpt_index(vaddr)].as_ref()
+    pub fn translate(&self, vaddr: u64, p: Prot) -> Option<u64> {
+        self.translate_detailed(vaddr, p).ok()
     }
 
-    fn pd_mut(&mut self, vaddr: u64) -> Option<&mut Box<Pd>> {
-        self.pd[pdpt_index(vaddr)].as_mut()
+    /// Translate a virtual address, reporting the fault the cpu would raise
+    /// if the access isn't allowed. Accesses are treated as supervisor
+    /// accesses with CR0.WP and EFER.NXE set. Non-canonical addresses raise
+    /// #GP on hardware, here they're reported as not present at the top level.
+    pub fn translate_detailed(&self, vaddr: u64, p: Prot) -> Result<u64, PageFault> {
+        self.translate_detailed_with(vaddr, p, &Context::default())
     }
 
-    fn iter(&self) -> IterPdpt {
-        IterPdpt {
-            inner: self,
-            pos: 0,
-        }
+    /// Translate a virtual address, applying the permission checks for the
+    /// given privilege level and control register state
+    pub fn translate_with(&self, vaddr: u64, p: Prot, ctx: &Context) -> Option<u64> {
+        self.translate_detailed_with(vaddr, p, ctx).ok()
     }
-}
 
-impl Default for Pdpt {
-    fn default() -> Self {
-        let pd: [Option<Box<Pd>>; 512] = unsafe { std::mem::zeroed() };
-        Self {
-            pd,
-            flags: PdptFlags::empty(),
-        }
+    pub fn translate_detailed_with(
+        &self,
+        vaddr: u64,
+        p: Prot,
+        ctx: &Context,
+    ) -> Result<u64, PageFault> {
+        self.translate_leaf(vaddr, p, ctx)
+            .map(|(paddr, _, _)| paddr)
     }
-}
 
-impl Index<usize> for Pdpt {
-    type Output = Option<Box<Pd>>;
+    // translate vaddr, also returning the level and bits of the leaf
+    fn translate_leaf(
+        &self,
+        vaddr: u64,
+        p: Prot,
+        ctx: &Context,
+    ) -> Result<(u64, Level, u64), PageFault> {
+        let mut walk = Walk::new(p, ctx);
+        let (mut slot, mut level) = (0, self.arena.root());
+
+        if !self.is_canonical(vaddr) {
+            return Err(walk.not_present(level));
+        }
+
+        loop {
+            let e = self.arena.get(slot, level.index(vaddr));
+
+            if e == 0 {
+                return Err(walk.not_present(level));
+            }
+
+            walk.entry(level, e)?;
+
+            if self.reserved(level, e) {
+                return Err(walk.reserved(level));
+            }
+
+            if arena::is_leaf(level, e) {
+                walk.check()?;
+                let paddr = leaf_addr(level, e) + (vaddr & (level.span() - 1));
+                return Ok((paddr, level, e));
+            }
 
-    fn index(&self, idx: usize) -> &Option<Box<Pd>> {
-        &self.pd[idx]
+            slot = child(e);
+            level = level.below();
+        }
     }
-}
 
-//
-// Page Table
-//
-bitflags! {
-    pub struct Flags : u64 {
-        const Present = 1 << 0;
-        const Writable = 1 << 1;
-        const User = 1 << 2;
-        const WriteThrough = 1 << 3;
-        const CacheDisabled = 1 << 4;
-        const Accessed = 1 << 5;
-        const Dirty = 1 << 6;
-        const NX = 1 << 63;
+    // whether an entry in the arena has reserved bits set
+    fn reserved(&self, level: Level, e: u64) -> bool {
+        match arena::is_leaf(level, e) {
+            true => reserved_bits(level, e, self.phys_bits) != 0,
+            false => e & RESERVED != 0,
+        }
     }
-}
 
-pub struct IterPageTable<'a> {
-    inner: &'a PageTable,
-    pos: usize,
-}
+    /// Translate a virtual address and update the accessed and dirty bits the
+    /// way the cpu would: every entry used by the walk is marked accessed, and
+    /// writes mark the leaf dirty. Nothing is marked if the access faults.
+    pub fn translate_and_mark(
+        &mut self,
+        vaddr: u64,
+        p: Prot,
+        ctx: &Context,
+    ) -> Result<u64, PageFault> {
+        let paddr = self.translate_detailed_with(vaddr, p, ctx)?;
+        let (mut slot, mut level) = (0, self.arena.root());
+
+        loop {
+            let idx = level.index(vaddr);
+            let mut e = self.arena.get(slot, idx) | Flags::Accessed.bits();
+            let leaf = arena::is_leaf(level, e);
+
+            if leaf && p.contains(Prot::W) {
+                e |= Flags::Dirty.bits();
+            }
 
-impl<'a> Iterator for IterPageTable<'a> {
-    type Item = Option<&'a Box<Pdpt>>;
+            self.arena.set(slot, idx, e);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.inner.pml4.len() {
-            None
-        } else {
-            self.pos += 1;
-            Some(self.inner.pml4[self.pos - 1].as_ref())
+            if leaf {
+                return Ok(paddr);
+            }
+
+            slot = child(e);
+            level = level.below();
         }
     }
-}
 
-pub struct PageTable {
-    pml4: [Option<Box<Pdpt>>; 512],
-}
+    /// Map a 4kb page. A 2mb or 1gb page already mapping `vaddr` is split, so
+    /// the rest of it stays mapped. Tables read with reserved bits set in an
+    /// entry above the page can't be changed.
+    pub fn insert(&mut self, vaddr: u64, paddr: u64, f: Flags) -> Result<(), Error> {
+        self.check_page(vaddr, paddr, 0x1000)?;
+        self.arena.split_to(vaddr, Level::Pt, split_bits)?;
 
-impl PageTable {
-    pub fn translate(&self, vaddr: u64, p: Prot) -> Option<u64> {
-        // pdpt
-        let pdpt = self.pdpt(vaddr)?;
-        if !pdpt.flags().contains(PdptFlags::Present) {
-            return None;
+        let pt = self
+            .arena
+            .walk_to(vaddr, Level::Pt, |old| table_flags(old, f));
+
+        let idx = pt_index(vaddr);
+        let e = self.arena.get(pt, idx);
+
+        let pte = PteFlags::from_bits_truncate(f.bits());
+        self.arena.set(pt, idx, paddr | pte.bits());
+
+        // overwriting a mapping can make the tables above it more
+        // restrictive, which merging can't express
+        if e != 0 {
+            self.update_flags(vaddr);
         }
-        if p.contains(Prot::W) && !pdpt.flags().contains(PdptFlags::Writable) {
-            return None;
+
+        Ok(())
+    }
+
+    /// Map a 2mb page, terminating the walk at the page directory. A 1gb page
+    /// already mapping `vaddr` is split, and smaller pages in the range are an
+    /// overlap.
+    pub fn insert_large(&mut self, vaddr: u64, paddr: u64, f: Flags) -> Result<(), Error> {
+        self.check_page(vaddr, paddr, 0x20_0000)?;
+        self.arena
+            .check_replace(vaddr, Level::Pd, |level, e| self.mapped(level, e))?;
+        self.arena.split_to(vaddr, Level::Pd, split_bits)?;
+
+        let pd = self
+            .arena
+            .walk_to(vaddr, Level::Pd, |old| table_flags(old, f));
+
+        let pde = PtFlags::from_bits_truncate(f.large()) | PtFlags::Size;
+        self.arena
+            .set_leaf(pd, Level::Pd, vaddr, paddr | pde.bits());
+
+        self.update_flags(vaddr);
+
+        Ok(())
+    }
+
+    /// Map a 1gb page, terminating the walk at the page directory pointer
+    /// table. Smaller pages in the range are an overlap.
+    pub fn insert_huge(&mut self, vaddr: u64, paddr: u64, f: Flags) -> Result<(), Error> {
+        self.check_page(vaddr, paddr, 0x4000_0000)?;
+        self.arena
+            .check_replace(vaddr, Level::Pdpt, |level, e| self.mapped(level, e))?;
+        self.arena.split_to(vaddr, Level::Pdpt, split_bits)?;
+
+        let pdpt = self
+            .arena
+            .walk_to(vaddr, Level::Pdpt, |old| table_flags(old, f));
+
+        let pdpte = PdFlags::from_bits_truncate(f.large()) | PdFlags::Size;
+        self.arena
+            .set_leaf(pdpt, Level::Pdpt, vaddr, paddr | pdpte.bits());
+
+        self.update_flags(vaddr);
+
+        Ok(())
+    }
+
+    // check that a page of `size` bytes can be mapped from `paddr` at `vaddr`
+    fn check_page(&self, vaddr: u64, paddr: u64, size: u64) -> Result<(), Error> {
+        if !self.is_canonical(vaddr) {
+            return Err(Error::NonCanonical(vaddr));
         }
-        if p.contains(Prot::X) && pdpt.flags().contains(PdptFlags::NX) {
-            return None;
+
+        for x in [vaddr, paddr].iter() {
+            if x & (size - 1) != 0 {
+                return Err(Error::Misaligned(*x));
+            }
         }
 
-        // pd
-        let pd = self.pd(vaddr)?;
+        check_paddr(paddr, size, self.phys_bits)
+    }
 
-        if !pd.flags().contains(PdFlags::Present) {
-            return None;
-        }
-        if p.contains(Prot::W) && !pd.flags().contains(PdFlags::Writable) {
-            return None;
+    // check that every address in vaddr..vaddr + len is canonical
+    fn check_canonical(&self, vaddr: u64, len: u64) -> Result<(), Error> {
+        let last = vaddr.checked_add(len.saturating_sub(1));
+
+        // the range can't leave the half of the address space it starts in
+        match last {
+            Some(last)
+                if self.is_canonical(vaddr)
+                    && self.is_canonical(last)
+                    && (vaddr ^ last) >> 63 == 0 =>
+            {
+                Ok(())
+            }
+            _ => Err(Error::NonCanonical(vaddr)),
         }
-        if p.contains(Prot::X) && pd.flags().contains(PdFlags::NX) {
-            return None;
+    }
+
+    /// Map `len` bytes of physical memory at `paddr` to `vaddr`, using 1gb and
+    /// 2mb pages wherever the alignment of both addresses allows. Nothing is
+    /// mapped if any part of the range is already mapped, or can only be
+    /// mapped through an entry with reserved bits.
+    pub fn map_range(&mut self, vaddr: u64, paddr: u64, len: u64, f: Flags) -> Result<(), Error> {
+        let end = check_range(vaddr, paddr, len, self.phys_bits)?;
+        self.check_canonical(vaddr, len)?;
+
+        let mapped = self
+            .arena
+            .mapped_in(vaddr, end, |level, e| self.mapped(level, e));
+
+        if let Some(mapped) = mapped {
+            return Err(Error::Overlap(mapped));
         }
 
-        // pt
-        let pt = self.pt(vaddr)?;
+        let reserved = arena::pages(vaddr, paddr, len)
+            .find(|&(v, _, level)| self.arena.reserved_above(v, level));
 
-        if !pt.flags().contains(PtFlags::Present) {
-            return None;
+        if let Some((v, _, _)) = reserved {
+            return Err(Error::Reserved(v));
         }
-        if p.contains(Prot::W) && !pt.flags().contains(PtFlags::Writable) {
-            return None;
+
+        for (v, p, level) in arena::pages(vaddr, paddr, len) {
+            match level {
+                Level::Pdpt => self.insert_huge(v, p, f)?,
+                Level::Pd => self.insert_large(v, p, f)?,
+                _ => self.insert(v, p, f)?,
+            }
         }
-        if p.contains(Prot::X) && pt.flags().contains(PtFlags::NX) {
-            return None;
+
+        Ok(())
+    }
+
+    // whether the entry a walk ends at maps memory. The memory behind an
+    // entry with reserved bits isn't mapped.
+    fn mapped(&self, level: Level, e: u64) -> bool {
+        e != 0 && !self.reserved(level, e)
+    }
+
+    /// Unmap the 4kb page containing `vaddr`, freeing any tables left empty.
+    /// A 2mb or 1gb page mapping it is split first, so the rest of it stays
+    /// mapped. Returns whether anything was mapped. Fails without changing
+    /// anything if the walk goes through an entry with reserved bits.
+    pub fn remove(&mut self, vaddr: u64) -> Result<bool, Error> {
+        self.check_canonical(vaddr, 1)?;
+
+        loop {
+            let (slot, level, e) = self.arena.find(vaddr);
+
+            // table entries with reserved bits are kept as they were read,
+            // and a leaf with them doesn't map anything
+            match self.mapped(level, e) {
+                false if e != 0 && !arena::is_leaf(level, e) => {
+                    return Err(Error::Reserved(vaddr));
+                }
+                false => return Ok(false),
+                true if level == Level::Pt => break,
+                true => self.arena.split(slot, level, vaddr, split_bits),
+            }
         }
 
-        // pte
-        let pte = self.pte(vaddr)?;
+        self.arena.remove(vaddr);
+
+        self.update_flags(vaddr);
+
+        Ok(true)
+    }
+
+    /// Replace the flags of every page overlapping `vaddr..vaddr + len`.
+    /// Large pages which are only partially covered are split. Unmapped
+    /// pages in the range are skipped, and a 4kb page of frame 0 left
+    /// without flags is removed.
+    pub fn protect(&mut self, vaddr: u64, len: u64, f: Flags) -> Result<(), Error> {
+        self.check_canonical(vaddr, len)?;
+
+        let end = vaddr.saturating_add(len);
+        let mut vaddr = vaddr & !0xfff;
+
+        while vaddr < end {
+            let next = self.protect_one(vaddr, end, f);
+
+            // the intermediate flags only need to be redone once per table
+            if next >= end || (next ^ vaddr) & !0x1f_ffff != 0 {
+                self.update_flags(vaddr);
+            }
 
-        if !pte.flags().contains(PteFlags::Present) {
-            return None;
+            vaddr = next;
         }
-        if p.contains(Prot::W) && !pte.flags().contains(PteFlags::Writable) {
-            return None;
+
+        Ok(())
+    }
+
+    // protect the page containing vaddr, returning the next address to look at
+    fn protect_one(&mut self, vaddr: u64, end: u64, f: Flags) -> u64 {
+        loop {
+            let (slot, level, e) = self.arena.find(vaddr);
+            let span = level.span();
+            let next = (vaddr | (span - 1)).saturating_add(1);
+
+            // the memory behind an entry with reserved bits isn't mapped
+            if e == 0 || self.reserved(level, e) {
+                return next;
+            }
+
+            if level == Level::Pt {
+                let pte = PteFlags::from_bits_truncate(f.bits());
+
+                // frame 0 without any flags is an empty entry, so the page is
+                // removed along with any tables it leaves empty
+                match (e & PADDR_MASK) | pte.bits() {
+                    0 => {
+                        self.arena.remove(vaddr);
+                    }
+                    e => self.arena.set(slot, pt_index(vaddr), e),
+                }
+
+                return next;
+            }
+
+            if vaddr & (span - 1) == 0 && next <= end {
+                let large = PdFlags::from_bits_truncate(f.large()) | PdFlags::Size;
+                self.arena
+                    .set(slot, level.index(vaddr), leaf_addr(level, e) | large.bits());
+                return next;
+            }
+
+            self.arena.split(slot, level, vaddr, split_bits);
         }
-        if p.contains(Prot::X) && pte.flags().contains(PteFlags::NX) {
-            return None;
+    }
+
+    // recompute the flags of every table on the walk to vaddr, bottom up. An
+    // entry the cpu has marked accessed stays accessed.
+    fn update_flags(&mut self, vaddr: u64) {
+        let path = self.arena.path(vaddr);
+
+        // the last table holds the leaf, every other one an entry pointing at
+        // the next
+        for &(slot, level) in path.iter().rev().skip(1) {
+            let idx = level.index(vaddr);
+            let e = self.arena.get(slot, idx);
+
+            let f = combined(
+                self.arena
+                    .page(child(e))
+                    .iter()
+                    .filter(|&&x| x != 0)
+                    .map(|&x| Flags::from_bits_truncate(x)),
+            );
+            let accessed = Flags::from_bits_truncate(e) & Flags::Accessed;
+
+            self.arena
+                .set(slot, idx, (e & PADDR_MASK) | (f | accessed).bits());
         }
+    }
 
-        Some(pte.paddr() + page_offset(vaddr) as u64)
+    pub fn commit(self) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        self.commit_at(0)
     }
 
-    pub fn insert(&mut self, vaddr: u64, paddr: u64, f: Flags) {
-        let pdpt = match self.pdpt_mut(vaddr) {
-            None => self.set_pdpt(vaddr, Pdpt::default()),
-            Some(x) => x,
-        };
+    /// Serialize the tables, allocating table pages contiguously from `base`.
+    /// Fails if the table pages would overlap memory mapped by the table.
+    pub fn commit_at(self, base: u64) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
+        self.check_commit(base)?;
 
-        pdpt.set_flags(pdpt.flags() | PdptFlags::from_bits_truncate(f.bits()));
+        Ok((base, self.arena.commit(base)?))
+    }
 
-        let pd = match pdpt.pd_mut(vaddr) {
-            None => pdpt.set_pd(vaddr, Pd::default()),
-            Some(x) => x,
-        };
+    /// Serialize the tables into a single host mapping, allocating table
+    /// pages contiguously from `base`. The table is kept, and the tables
+    /// stay mapped for as long as the returned region.
+    pub fn commit_contiguous(&self, base: u64) -> Result<TableRegion, Error> {
+        self.check_commit(base)?;
 
-        pd.set_flags(pd.flags() | PdFlags::from_bits_truncate(f.bits()));
+        Ok(TableRegion::commit(&self.arena, base, base, false)?)
+    }
 
-        let pt = match pd.pt_mut(vaddr) {
-            None => pd.set_pt(vaddr, Pt::default()),
-            Some(x) => x,
-        };
+    /// Like `commit_contiguous`, with the mapping backed by 2mb huge pages.
+    /// Fails if the host has no huge pages reserved.
+    pub fn commit_huge(&self, base: u64) -> Result<TableRegion, Error> {
+        self.check_commit(base)?;
 
-        pt.set_flags(pt.flags() | PtFlags::from_bits_truncate(f.bits()));
+        Ok(TableRegion::commit(&self.arena, base, base, true)?)
+    }
 
-        let pte = match pt.pte_mut(vaddr) {
-            None => pt.set_pte(vaddr, Pte::default()),
-            Some(x) => x,
-        };
+    // check that the tables can be allocated from `base` without
+    // overlapping memory mapped by the table
+    fn check_commit(&self, base: u64) -> Result<(), Error> {
+        let end = self.commit_region(base, self.tables())?;
 
-        pte.set_flags(pte.flags() | PteFlags::from_bits_truncate(f.bits()));
-        pte.set_paddr(paddr);
+        match self.overlap(base, end) {
+            Some(paddr) => Err(Error::TableOverlap(paddr)),
+            None => Ok(()),
+        }
     }
 
-    pub fn commit(self) -> Result<(u64, BTreeMap<u64, MmapMut>), Error> {
-        let mut r = BTreeMap::new();
-        let mut base = 0;
+    // like `commit_region`, with the tables also addressable by the cpu
+    fn commit_region(&self, base: u64, tables: u64) -> Result<u64, Error> {
+        let end = commit_region(base, tables)?;
+        check_paddr(base, tables * 0x1000, self.phys_bits)?;
 
-        let mut pml4_backing = MmapMut::map_anon(0x1000)?;
+        Ok(end)
+    }
 
-        let pml4_data: &mut [u64] = unsafe {
-            slice::from_raw_parts_mut(
-                pml4_backing.as_mut_ptr() as *mut _,
-                0x1000 / mem::size_of::<u64>(),
-            )
-        };
+    // number of pages the table will need when committed
+    fn tables(&self) -> u64 {
+        self.arena.tables() as u64
+    }
 
-        let pml4_paddr = commit_next(&mut base);
+    fn overlap(&self, start: u64, end: u64) -> Option<u64> {
+        self.arena
+            .leaves()
+            .map(|(level, e)| (leaf_addr(level, e), level.span()))
+            .find(|&(paddr, size)| overlaps(start, end, paddr, size))
+            .map(|(paddr, _)| paddr)
+    }
 
-        for (pp, pdpt) in self
-            .iter()
-            .enumerate()
-            .filter_map(|(pp, pdpt)| pdpt.map(|x| (pp, x)))
-        {
-            let mut pdpt_backing = MmapMut::map_anon(0x1000)?;
+    // the non-empty entry at `level` on the walk to vaddr
+    fn entry(&self, vaddr: u64, level: Level) -> Option<Entry> {
+        let slot = self.arena.table(vaddr, level)?;
+        let bits = self.arena.get(slot, level.index(vaddr));
 
-            let pdpt_data: &mut [u64] = unsafe {
-                slice::from_raw_parts_mut(
-                    pdpt_backing.as_mut_ptr() as *mut _,
-                    0x1000 / mem::size_of::<u64>(),
-                )
-            };
+        match bits {
+            0 => None,
+            _ => Some(Entry { level, bits }),
+        }
+    }
 
-            let pdpt_paddr = commit_next(&mut base);
+    /// The pml5 entry pointing at the pml4 for `vaddr`, only present with
+    /// 5-level paging
+    pub fn pml4(&self, vaddr: u64) -> Option<Entry> {
+        self.entry(vaddr, Level::Pml5)
+    }
 
-            for (qq, pd) in pdpt
-                .iter()
-                .enumerate()
-                .filter_map(|(qq, pd)| pd.map(|x| (qq, x)))
-            {
-                let mut pd_backing = MmapMut::map_anon(0x1000)?;
+    /// The pml4 entry pointing at the pdpt for `vaddr`
+    pub fn pdpt(&self, vaddr: u64) -> Option<Entry> {
+        self.entry(vaddr, Level::Pml4)
+    }
 
-                let pd_data: &mut [u64] = unsafe {
-                    slice::from_raw_parts_mut(
-                        pd_backing.as_mut_ptr() as *mut _,
-                        0x1000 / mem::size_of::<u64>(),
-                    )
-                };
+    /// The pdpt entry pointing at the pd for `vaddr`, or mapping its 1gb page
+    pub fn pd(&self, vaddr: u64) -> Option<Entry> {
+        self.entry(vaddr, Level::Pdpt)
+    }
 
-                let pd_paddr = commit_next(&mut base);
+    /// The pd entry pointing at the pt for `vaddr`, or mapping its 2mb page
+    pub fn pt(&self, vaddr: u64) -> Option<Entry> {
+        self.entry(vaddr, Level::Pd)
+    }
 
-                for (rr, pt) in pd
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(rr, pt)| pt.map(|x| (rr, x)))
-                {
-                    let mut pt_backing = MmapMut::map_anon(0x1000)?;
-                    let pt_paddr = commit_next(&mut base);
-
-                    let pt_data: &mut [u64] = unsafe {
-                        slice::from_raw_parts_mut(
-                            pt_backing.as_mut_ptr() as *mut _,
-                            0x1000 / mem::size_of::<u64>(),
-                        )
-                    };
+    pub fn pte(&self, vaddr: u64) -> Option<Entry> {
+        self.entry(vaddr, Level::Pt)
+    }
 
-                    for (ss, pte) in pt
-                        .iter()
-                        .enumerate()
-                        .filter_map(|(ss, pte)| pte.map(|x| (ss, x)))
-                    {
-                        pt_data[ss] = pte.paddr() | pte.flags().bits();
-                    }
+    /// Iterate every present leaf mapping in virtual address order
+    pub fn mappings(&self) -> Mappings<'_> {
+        Mappings {
+            inner: self,
+            pos: 0,
+            end: 1 << self.paging.bits(),
+        }
+    }
+}
 
-                    pd_data[rr] = pt_paddr | pt.flags().bits();
-                    r.insert(pt_paddr, pt_backing);
-                }
+impl Default for PageTable {
+    fn default() -> Self {
+        Self::new(Paging::default())
+    }
+}
 
-                pdpt_data[qq] = pd_paddr | pd.flags().bits();
-                r.insert(pd_paddr, pd_backing);
-            }
+//
+// Faults
+//
+bitflags! {
+    /// The #PF error code
+    pub struct PfCode : u32 {
+        /// Set for protection violations, clear if the page wasn't present
+        const Present = 1 << 0;
+        const Write = 1 << 1;
+        const User = 1 << 2;
+        const Reserved = 1 << 3;
+        const Fetch = 1 << 4;
+        const ProtectionKey = 1 << 5;
+    }
+}
+
+/// A paging structure, used to report which entry stopped a walk
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Pml5,
+    Pml4,
+    Pdpt,
+    Pd,
+    Pt,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Pml5 => "pml5",
+            Level::Pml4 => "pml4",
+            Level::Pdpt => "pdpt",
+            Level::Pd => "pd",
+            Level::Pt => "pt",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageFault {
+    pub code: PfCode,
+    /// The table holding the entry which caused the fault
+    pub level: Level,
+}
+
+impl PageFault {
+    fn new(level: Level, present: bool, p: Prot, ctx: &Context) -> Self {
+        let mut code = PfCode::empty();
+
+        code.set(PfCode::Present, present);
+        code.set(PfCode::Write, p.contains(Prot::W));
+        code.set(PfCode::User, ctx.is_user());
+        code.set(PfCode::Fetch, p.contains(Prot::X));
+
+        Self { code, level }
+    }
+}
 
-            pml4_data[pp] = pdpt_paddr | pdpt.flags().bits();
-            r.insert(pdpt_paddr, pdpt_backing);
+impl fmt::Display for PageFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#PF({:#x}) at {}", self.code.bits(), self.level)
+    }
+}
+
+impl std::error::Error for PageFault {}
+
+/// The processor state which affects permission checks during translation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Context {
+    pub cpl: u8,
+    /// CR0.WP, supervisor writes honor read-only pages
+    pub wp: bool,
+    /// CR4.SMEP, supervisor fetches from user pages fault
+    pub smep: bool,
+    /// CR4.SMAP, supervisor data accesses to user pages fault unless AC is set
+    pub smap: bool,
+    /// EFLAGS.AC
+    pub ac: bool,
+    /// PKRU, checked against the key of user pages when CR4.PKE is set
+    pub pkru: Option<u32>,
+    /// IA32_PKRS, checked against the key of supervisor pages when CR4.PKS is
+    /// set
+    pub pkrs: Option<u32>,
+}
+
+impl Context {
+    /// A supervisor context with only CR0.WP set
+    pub fn supervisor() -> Self {
+        Self {
+            cpl: 0,
+            wp: true,
+            smep: false,
+            smap: false,
+            ac: false,
+            pkru: None,
+            pkrs: None,
         }
+    }
 
-        r.insert(pml4_paddr, pml4_backing);
+    pub fn user() -> Self {
+        Self {
+            cpl: 3,
+            ..Self::supervisor()
+        }
+    }
 
-        Ok((pml4_paddr, r))
+    fn is_user(&self) -> bool {
+        self.cpl == 3
     }
+}
 
-    pub fn set_pdpt(&mut self, vaddr: u64, v: Pdpt) -> &mut Pdpt {
-        self.pml4[pml4_index(vaddr)] = Some(Box::new(v));
-        self.pml4[pml4_index(vaddr)].as_mut().unwrap()
+impl Default for Context {
+    fn default() -> Self {
+        Self::supervisor()
     }
+}
 
-    pub fn pdpt(&self, vaddr: u64) -> Option<&Box<Pdpt>> {
-        self.pml4[pml4_index(vaddr)].as_ref()
+// The entries seen during a translation. Not present entries stop the walk
+// immediately, while permissions are only checked once it's complete, the
+// same as the cpu.
+struct Walk<'a> {
+    p: Prot,
+    ctx: &'a Context,
+    entries: [(Level, Flags); 5],
+    len: usize,
+}
+
+impl<'a> Walk<'a> {
+    fn new(p: Prot, ctx: &'a Context) -> Self {
+        Self {
+            p,
+            ctx,
+            entries: [(Level::Pml5, Flags::empty()); 5],
+            len: 0,
+        }
     }
 
-    pub fn pdpt_mut(&mut self, vaddr: u64) -> Option<&mut Box<Pdpt>> {
-        self.pml4[pml4_index(vaddr)].as_mut()
+    fn not_present(&self, level: Level) -> PageFault {
+        PageFault::new(level, false, self.p, self.ctx)
     }
 
-    pub fn pd(&self, vaddr: u64) -> Option<&Box<Pd>> {
-        let pdpt = self.pdpt(vaddr)?;
-        pdpt.pd(vaddr)
+    fn reserved(&self, level: Level) -> PageFault {
+        let mut pf = PageFault::new(level, true, self.p, self.ctx);
+        pf.code.insert(PfCode::Reserved);
+        pf
     }
 
-    pub fn pd_mut(&mut self, vaddr: u64) -> Option<&mut Box<Pd>> {
-        let pdpt = self.pdpt_mut(vaddr)?;
-        pdpt.pd_mut(vaddr)
+    fn entry(&mut self, level: Level, bits: u64) -> Result<(), PageFault> {
+        let f = Flags::from_bits_truncate(bits);
+
+        if !f.contains(Flags::Present) {
+            return Err(self.not_present(level));
+        }
+
+        self.entries[self.len] = (level, f);
+        self.len += 1;
+
+        Ok(())
     }
 
-    pub fn pt(&self, vaddr: u64) -> Option<&Box<Pt>> {
-        let pd = self.pd(vaddr)?;
-        pd.pt(vaddr)
+    // the first entry in the walk without all of the given flags
+    fn lacking(&self, f: Flags) -> Option<Level> {
+        self.entries[..self.len]
+            .iter()
+            .find(|(_, x)| !x.contains(f))
+            .map(|(level, _)| *level)
     }
 
-    pub fn pt_mut(&mut self, vaddr: u64) -> Option<&mut Box<Pt>> {
-        let pd = self.pd_mut(vaddr)?;
-        pd.pt_mut(vaddr)
+    // a user mode page needs the user bit at every level
+    fn user_page(&self) -> bool {
+        self.lacking(Flags::User).is_none()
     }
 
-    pub fn pte(&self, vaddr: u64) -> Option<&Box<Pte>> {
-        let pt = self.pt(vaddr)?;
-        pt.pte(vaddr)
+    fn check(&self) -> Result<(), PageFault> {
+        let fault = |level| Err(PageFault::new(level, true, self.p, self.ctx));
+        let leaf = self.entries[self.len - 1].0;
+        let user_page = self.user_page();
+        let data = self.p.intersects(Prot::R | Prot::W);
+
+        if self.ctx.is_user() {
+            if let Some(level) = self.lacking(Flags::User) {
+                return fault(level);
+            }
+        } else {
+            if self.p.contains(Prot::X) && user_page && self.ctx.smep {
+                return fault(leaf);
+            }
+            if data && user_page && self.ctx.smap && !self.ctx.ac {
+                return fault(leaf);
+            }
+        }
+
+        // supervisor writes ignore read-only pages unless CR0.WP is set
+        if self.p.contains(Prot::W) && (self.ctx.is_user() || self.ctx.wp) {
+            if let Some(level) = self.lacking(Flags::Writable) {
+                return fault(level);
+            }
+        }
+
+        if self.p.contains(Prot::X) {
+            let nx = self.entries[..self.len]
+                .iter()
+                .find(|(_, x)| x.contains(Flags::NX));
+
+            if let Some((level, _)) = nx {
+                return fault(*level);
+            }
+        }
+
+        // protection keys only restrict data accesses, each key has an access
+        // disable bit followed by a write disable bit
+        let pkr = match user_page {
+            true => self.ctx.pkru,
+            false => self.ctx.pkrs,
+        };
+
+        if let (Some(pkr), true) = (pkr, data) {
+            let (leaf, f) = self.entries[self.len - 1];
+            let rights = pkr >> (f.key() * 2);
+            let ad = rights & 1 != 0;
+            let wd = rights & 2 != 0 && (self.ctx.is_user() || self.ctx.wp);
+
+            if ad || (wd && self.p.contains(Prot::W)) {
+                let mut pf = PageFault::new(leaf, true, self.p, self.ctx);
+                pf.code.insert(PfCode::ProtectionKey);
+                return Err(pf);
+            }
+        }
+
+        Ok(())
     }
+}
+
+//
+// Mappings
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    /// Canonical virtual address
+    pub vaddr: u64,
+    pub paddr: u64,
+    /// Size of the page, or of the whole run when coalesced
+    pub size: u64,
+    /// The leaf's flags, restricted by the permissions of every table above it
+    pub flags: Flags,
+}
+
+// whether `b` continues `a` both virtually and physically, with the same flags
+fn contiguous(a: &Mapping, b: &Mapping) -> bool {
+    a.vaddr.wrapping_add(a.size) == b.vaddr
+        && a.paddr.wrapping_add(a.size) == b.paddr
+        && a.flags == b.flags
+}
+
+// restrict a leaf's flags by those of a table entry above it
+fn restrict(leaf: Flags, table: Flags) -> Flags {
+    let allowed = table | !(Flags::Present | Flags::Writable | Flags::User);
+
+    (leaf & allowed) | (table & Flags::NX)
+}
+
+pub struct Mappings<'a> {
+    inner: &'a PageTable,
+    // position in the linear address space, without sign extension
+    pos: u64,
+    end: u64,
+}
 
-    pub fn pte_mut(&mut self, vaddr: u64) -> Option<&mut Box<Pte>> {
-        let pt = self.pt_mut(vaddr)?;
-        pt.pte_mut(vaddr)
+impl<'a> Mappings<'a> {
+    /// Merge runs of mappings which are virtually and physically contiguous
+    /// and have the same flags
+    pub fn coalesce(self) -> Coalesce<Self> {
+        Coalesce {
+            inner: self.peekable(),
+        }
     }
 
-    pub fn iter(&self) -> IterPageTable {
-        IterPageTable {
-            inner: self,
-            pos: 0,
+    fn mapping(&self, pos: u64, paddr: u64, size: u64, leaf: Flags, tables: &[Flags]) -> Mapping {
+        let flags = tables.iter().fold(leaf, |f, t| restrict(f, *t));
+
+        Mapping {
+            vaddr: self.inner.paging.canonical(pos),
+            paddr,
+            size,
+            flags,
         }
     }
 }
 
-impl Index<usize> for PageTable {
-    type Output = Option<Box<Pdpt>>;
+impl<'a> Iterator for Mappings<'a> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        let arena = &self.inner.arena;
 
-    fn index(&self, idx: usize) -> &Option<Box<Pdpt>> {
-        &self.pml4[idx]
+        while self.pos < self.end {
+            let pos = self.pos;
+            let (mut slot, mut level) = (0, arena.root());
+            let mut tables = vec![];
+
+            loop {
+                let e = arena.get(slot, level.index(pos));
+                self.pos = (pos | (level.span() - 1)).saturating_add(1);
+
+                // the cpu faults on entries which aren't present or have
+                // reserved bits rather than using them
+                if e & Flags::Present.bits() == 0 || self.inner.reserved(level, e) {
+                    break;
+                }
+
+                if arena::is_leaf(level, e) {
+                    let f = match level {
+                        Level::Pt => Flags::from_bits_truncate(e),
+                        _ => Flags::from_large(e),
+                    };
+
+                    return Some(self.mapping(pos, leaf_addr(level, e), level.span(), f, &tables));
+                }
+
+                tables.push(Flags::from_bits_truncate(e));
+                slot = child(e);
+                level = level.below();
+            }
+        }
+
+        None
     }
 }
 
-impl Default for PageTable {
-    fn default() -> Self {
-        let pml4: [Option<Box<Pdpt>>; 512] = unsafe { std::mem::zeroed() };
+pub struct Coalesce<I: Iterator<Item = Mapping>> {
+    inner: Peekable<I>,
+}
+
+impl<I: Iterator<Item = Mapping>> Iterator for Coalesce<I> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        let mut r = self.inner.next()?;
+
+        while let Some(next) = self.inner.peek() {
+            if !contiguous(&r, next) {
+                break;
+            }
+
+            r.size += next.size;
+            self.inner.next();
+        }
 
-        Self { pml4 }
+        Some(r)
     }
 }
@@ -0,0 +1,168 @@
+//
+// Live tables
+//
+use crate::arena::leaf_addr;
+use crate::{overlaps, Error, Flags, PageTable, TableRegion};
+
+// the most table pages a single change can add: a table at every level below
+// a pml5, or splitting a 1gb page down to 4kb pages at both ends of a range
+const MAX_NEW_TABLES: usize = 4;
+
+/// A table committed into a region which is kept in sync with it. Each
+/// table page keeps its guest physical address for as long as it's in use,
+/// so a change only rewrites the pages it touches. Rewritten pages lose any
+/// accessed and dirty bits the guest set in them.
+pub struct LiveTable {
+    table: PageTable,
+    region: TableRegion,
+}
+
+impl PageTable {
+    /// Commit the tables into a region with room for `capacity` table pages
+    /// from `base`, which later changes made through the returned
+    /// `LiveTable` are written into in place
+    pub fn commit_live(self, base: u64, capacity: usize) -> Result<LiveTable, Error> {
+        let end = self.commit_region(base, capacity as u64)?;
+
+        if let Some(paddr) = self.overlap(base, end) {
+            return Err(Error::TableOverlap(paddr));
+        }
+
+        let mut r = LiveTable {
+            region: TableRegion::new(base, base, capacity, false)?,
+            table: self,
+        };
+
+        let slots: Vec<usize> = (0..r.table.arena.slots()).collect();
+        r.write(&slots)?;
+        r.table.arena.clear_dirty();
+
+        Ok(r)
+    }
+}
+
+impl LiveTable {
+    pub fn table(&self) -> &PageTable {
+        &self.table
+    }
+
+    /// The table, for changes without a method here. They only reach the
+    /// region on the next `sync`.
+    pub fn table_mut(&mut self) -> &mut PageTable {
+        &mut self.table
+    }
+
+    pub fn region(&self) -> &TableRegion {
+        &self.region
+    }
+
+    pub fn region_mut(&mut self) -> &mut TableRegion {
+        &mut self.region
+    }
+
+    /// The value to load into CR3
+    pub fn root(&self) -> u64 {
+        self.region.root()
+    }
+
+    /// Rewrite the table pages changed since the last sync, returning their
+    /// guest physical addresses so only those need flushing. Nothing is
+    /// written if the tables have outgrown the region or a new mapping
+    /// overlaps it, and the changes stay pending.
+    pub fn sync(&mut self) -> Result<Vec<u64>, Error> {
+        let r = self.write(&self.table.arena.dirty())?;
+        self.table.arena.clear_dirty();
+
+        Ok(r)
+    }
+
+    /// Map a 4kb page, see `PageTable::insert`. Nothing changes if the page
+    /// overlaps the region or the tables would outgrow it.
+    pub fn insert(&mut self, vaddr: u64, paddr: u64, f: Flags) -> Result<Vec<u64>, Error> {
+        self.check_overlap(paddr, 0x1000)?;
+        self.change(|x| x.insert(vaddr, paddr, f))
+    }
+
+    pub fn insert_large(&mut self, vaddr: u64, paddr: u64, f: Flags) -> Result<Vec<u64>, Error> {
+        self.check_overlap(paddr, 0x20_0000)?;
+        self.change(|x| x.insert_large(vaddr, paddr, f))
+    }
+
+    pub fn insert_huge(&mut self, vaddr: u64, paddr: u64, f: Flags) -> Result<Vec<u64>, Error> {
+        self.check_overlap(paddr, 0x4000_0000)?;
+        self.change(|x| x.insert_huge(vaddr, paddr, f))
+    }
+
+    /// Unmap the 4kb page containing `vaddr`, see `PageTable::remove`.
+    /// Nothing is dirtied if it wasn't mapped.
+    pub fn remove(&mut self, vaddr: u64) -> Result<Vec<u64>, Error> {
+        self.change(|x| x.remove(vaddr).map(|_| ()))
+    }
+
+    pub fn protect(&mut self, vaddr: u64, len: u64, f: Flags) -> Result<Vec<u64>, Error> {
+        self.change(|x| x.protect(vaddr, len, f))
+    }
+
+    // check that a new page doesn't overlap the region
+    fn check_overlap(&self, paddr: u64, size: u64) -> Result<(), Error> {
+        let base = self.region.gpa();
+        let end = base + self.region.len() as u64 * 0x1000;
+
+        match overlaps(base, end, paddr, size) {
+            true => Err(Error::TableOverlap(paddr)),
+            false => Ok(()),
+        }
+    }
+
+    // make a change to the table and sync it, putting the table back if
+    // either fails. New mappings are checked against the region up front, so
+    // only a change which could outgrow the region needs a copy to go back to.
+    fn change<F>(&mut self, change: F) -> Result<Vec<u64>, Error>
+    where
+        F: FnOnce(&mut PageTable) -> Result<(), Error>,
+    {
+        let saved = match self.table.arena.slots() + MAX_NEW_TABLES > self.region.len() {
+            true => Some(self.table.clone()),
+            false => None,
+        };
+
+        let r = change(&mut self.table).and_then(|_| self.sync());
+
+        if let (Err(_), Some(saved)) = (&r, saved) {
+            self.table = saved;
+        }
+
+        r
+    }
+
+    // write the tables in `slots` to their pages, each slot's page being at
+    // the same offset in the region
+    fn write(&mut self, slots: &[usize]) -> Result<Vec<u64>, Error> {
+        let arena = &self.table.arena;
+        let base = self.region.gpa();
+        let end = base + self.region.len() as u64 * 0x1000;
+        let gpa = |slot: usize| base + slot as u64 * 0x1000;
+
+        if arena.slots() > self.region.len() {
+            return Err(Error::Capacity(arena.slots()));
+        }
+
+        // only the changed tables can hold new mappings
+        for &slot in slots {
+            let overlap = arena
+                .leaves_in(slot)
+                .map(|(level, e)| (leaf_addr(level, e), level.span()))
+                .find(|&(paddr, size)| overlaps(base, end, paddr, size));
+
+            if let Some((paddr, _)) = overlap {
+                return Err(Error::TableOverlap(paddr));
+            }
+        }
+
+        for &slot in slots {
+            self.region.write(slot, &arena.committed_page(slot, gpa));
+        }
+
+        Ok(slots.iter().map(|&slot| gpa(slot)).collect())
+    }
+}
@@ -1,27 +1,105 @@
-use std::io::{self, Write};
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::process;
 
-use pt::{Flags, PageTable};
+use pt::{Dump, Flags, PageTable, Paging};
 
-fn main() {
+const USAGE: &str = "usage: pt [--la57] [--cr3 <gpa>] [--phys-bits <n>] [<image> [<vaddr>...]]
+
+Dump the tables at cr3 in a raw image of guest physical memory, where file
+offsets are guest physical addresses, or explain the walk for each vaddr.
+Address bits at and above --phys-bits (default 52) are reserved. Without an
+image, a sample table is dumped instead.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse(s: &str) -> u64 {
+    let digits = s.trim_start_matches("0x").replace('_', "");
+
+    u64::from_str_radix(&digits, 16).unwrap_or_else(|_| usage())
+}
+
+fn sample() -> PageTable {
     let mut pt = PageTable::default();
 
-    pt.insert(0x4141_0000, 0x8181_0000, Flags::User | Flags::Present);
+    pt.insert(0x4141_0000, 0x8181_0000, Flags::User | Flags::Present)
+        .unwrap();
     pt.insert(
         0x1234_5000,
         0x6789_0000,
         Flags::User | Flags::Present | Flags::Writable,
-    );
+    )
+    .unwrap();
+
+    pt
+}
+
+fn main() {
+    let mut paging = Paging::Level4;
+    let mut cr3 = 0;
+    let mut phys_bits = 52;
+    let mut positional = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--la57" => paging = Paging::Level5,
+            "--cr3" => cr3 = parse(&args.next().unwrap_or_else(|| usage())),
+            "--phys-bits" => {
+                phys_bits = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "-h" | "--help" => usage(),
+            _ => positional.push(arg),
+        }
+    }
+
+    let path = match positional.first() {
+        Some(path) => path,
+        None => {
+            let pt = sample();
+            print!("{}\n{}", pt.dump(), pt.explain(0x4141_0000));
+            return;
+        }
+    };
 
+    let image = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
 
-    let (pml4, pagetable) = pt.commit().unwrap();
-    assert_eq!(pml4, 0);
+    let read = |gpa: u64, buf: &mut [u8]| {
+        let start = gpa as usize;
 
-    let mut last = 0;
-    for page in pagetable {
-        assert_eq!(page.0, last);
+        match image.get(start..start + buf.len()) {
+            Some(data) => {
+                buf.copy_from_slice(data);
+                Ok(())
+            }
+            None => Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("{:#x} is outside of the image", gpa),
+            )),
+        }
+    };
 
-        io::stdout().write_all(&page.1).unwrap();
+    let dump = Dump::from_physical(paging, phys_bits, cr3, read).unwrap_or_else(|e| {
+        eprintln!("reading the tables at {:#x}: {}", cr3, e);
+        process::exit(1);
+    });
 
-        last += 0x1000;
+    match &positional[1..] {
+        [] => print!("{}", dump),
+        vaddrs => {
+            for vaddr in vaddrs {
+                print!("{}", dump.explain(parse(vaddr)));
+            }
+        }
     }
 }
@@ -0,0 +1,121 @@
+//
+// Nested (guest and EPT) translation
+//
+use std::fmt;
+use std::io::Error;
+
+use crate::{
+    Context, EptTable, EptViolation, Level, PageFault, Paging, PdFlags, Prot, Walk, PADDR_MASK,
+};
+
+/// The fault a nested translation stopped at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NestedFault {
+    /// A #PF raised by the guest's own tables
+    Guest(PageFault),
+    /// An EPT violation or misconfiguration, either translating the address
+    /// of the entry in the guest paging structure at the given level, or for
+    /// `None`, the final access
+    Ept(EptViolation, Option<Level>),
+}
+
+impl fmt::Display for NestedFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NestedFault::Guest(pf) => write!(f, "guest {}", pf),
+            NestedFault::Ept(v, Some(level)) => write!(f, "{} reading the guest {}", v, level),
+            NestedFault::Ept(v, None) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl std::error::Error for NestedFault {}
+
+impl EptTable {
+    /// Translate a guest virtual address through the guest tables at `cr3`
+    /// and then this EPT, walking both dimensions the way the cpu does: each
+    /// guest paging structure entry is located through the EPT before it's
+    /// read, and the guest physical address of the access is translated
+    /// last. `read` fills a buffer from a host physical address. The outer
+    /// error is a failed read, the inner one is the fault the access raises.
+    pub fn translate_nested<F>(
+        &self,
+        paging: Paging,
+        cr3: u64,
+        gva: u64,
+        p: Prot,
+        ctx: &Context,
+        mut read: F,
+    ) -> Result<Result<u64, NestedFault>, Error>
+    where
+        F: FnMut(u64, &mut [u8]) -> Result<(), Error>,
+    {
+        let levels: &[(Level, u64)] = match paging {
+            Paging::Level4 => &[
+                (Level::Pml4, 39),
+                (Level::Pdpt, 30),
+                (Level::Pd, 21),
+                (Level::Pt, 12),
+            ],
+            Paging::Level5 => &[
+                (Level::Pml5, 48),
+                (Level::Pml4, 39),
+                (Level::Pdpt, 30),
+                (Level::Pd, 21),
+                (Level::Pt, 12),
+            ],
+        };
+
+        let mut walk = Walk::new(p, ctx);
+
+        if !paging.is_canonical(gva) {
+            return Ok(Err(NestedFault::Guest(walk.not_present(levels[0].0))));
+        }
+
+        let mut table = cr3 & PADDR_MASK;
+        let mut gpa = 0;
+
+        for &(level, shift) in levels {
+            let entry = table + ((gva >> shift) & 0x1ff) * 8;
+
+            // the guest's tables are only ever read, accessed and dirty
+            // updates aside
+            let hpa = match self.translate_mode(entry, Prot::R, false) {
+                Ok(hpa) => hpa,
+                Err(v) => return Ok(Err(NestedFault::Ept(v, Some(level)))),
+            };
+
+            let mut buf = [0u8; 8];
+            read(hpa, &mut buf)?;
+            let e = u64::from_le_bytes(buf);
+
+            if let Err(pf) = walk.entry(level, e) {
+                return Ok(Err(NestedFault::Guest(pf)));
+            }
+
+            let size = match level {
+                Level::Pdpt if e & PdFlags::Size.bits() != 0 => 0x4000_0000,
+                Level::Pd if e & PdFlags::Size.bits() != 0 => 0x20_0000,
+                Level::Pt => 0x1000,
+                _ => {
+                    table = e & PADDR_MASK;
+                    continue;
+                }
+            };
+
+            gpa = (e & PADDR_MASK & !(size - 1)) + (gva & (size - 1));
+            break;
+        }
+
+        if let Err(pf) = walk.check() {
+            return Ok(Err(NestedFault::Guest(pf)));
+        }
+
+        // with mode-based execute control, the guest's translation decides
+        // between supervisor and user execute
+        match self.translate_mode(gpa, p, walk.user_page()) {
+            Ok(hpa) => Ok(Ok(hpa)),
+            Err(v) => Ok(Err(NestedFault::Ept(v, None))),
+        }
+    }
+}
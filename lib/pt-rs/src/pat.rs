@@ -0,0 +1,90 @@
+use crate::{Flags, Mapping};
+
+/// The value of IA32_PAT at reset, which keeps the memory types selected by
+/// PWT and PCD alone the same as without PAT
+pub const PAT_DEFAULT: u64 = 0x0007_0406_0007_0406;
+
+/// A memory type, as stored in an entry of IA32_PAT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemoryType {
+    Uncacheable,
+    WriteCombining,
+    WriteThrough,
+    WriteProtected,
+    WriteBack,
+    /// UC-, which MTRRs can override to write combining
+    UncacheableMinus,
+}
+
+impl MemoryType {
+    pub(crate) fn encoding(self) -> u64 {
+        match self {
+            MemoryType::Uncacheable => 0,
+            MemoryType::WriteCombining => 1,
+            MemoryType::WriteThrough => 4,
+            MemoryType::WriteProtected => 5,
+            MemoryType::WriteBack => 6,
+            MemoryType::UncacheableMinus => 7,
+        }
+    }
+
+    pub(crate) fn from_encoding(x: u64) -> Option<Self> {
+        match x {
+            0 => Some(MemoryType::Uncacheable),
+            1 => Some(MemoryType::WriteCombining),
+            4 => Some(MemoryType::WriteThrough),
+            5 => Some(MemoryType::WriteProtected),
+            6 => Some(MemoryType::WriteBack),
+            7 => Some(MemoryType::UncacheableMinus),
+            _ => None,
+        }
+    }
+}
+
+// the PAT entry selected by PAT, PCD and PWT, in that order from the top
+fn pat_index(f: Flags) -> u64 {
+    let mut r = 0;
+
+    if f.contains(Flags::WriteThrough) {
+        r |= 1;
+    }
+    if f.contains(Flags::CacheDisabled) {
+        r |= 2;
+    }
+    if f.contains(Flags::AttributeTable) {
+        r |= 4;
+    }
+
+    r
+}
+
+impl Flags {
+    /// Select memory type `ty` for the pages mapped with these flags, using
+    /// the first entry of `pat` holding it. The PAT bit is placed correctly
+    /// for each page size when the flags are inserted. Returns `None` if no
+    /// entry of `pat` holds the type.
+    pub fn with_memory_type(self, ty: MemoryType, pat: u64) -> Option<Flags> {
+        let idx = (0..8).find(|ii| (pat >> (ii * 8)) & 7 == ty.encoding())?;
+        let mut f = self - Flags::WriteThrough - Flags::CacheDisabled - Flags::AttributeTable;
+
+        f.set(Flags::WriteThrough, idx & 1 != 0);
+        f.set(Flags::CacheDisabled, idx & 2 != 0);
+        f.set(Flags::AttributeTable, idx & 4 != 0);
+
+        Some(f)
+    }
+
+    /// The memory type selected by these flags, or `None` if the entry of
+    /// `pat` they select holds a reserved encoding
+    pub fn memory_type(self, pat: u64) -> Option<MemoryType> {
+        MemoryType::from_encoding((pat >> (pat_index(self) * 8)) & 7)
+    }
+}
+
+impl Mapping {
+    /// The memory type of the mapping given the value of IA32_PAT
+    pub fn memory_type(&self, pat: u64) -> Option<MemoryType> {
+        self.flags.memory_type(pat)
+    }
+}
@@ -0,0 +1,193 @@
+//
+// Contiguous table regions
+//
+use std::io::{Error, ErrorKind};
+use std::slice;
+
+use memmap::MmapMut;
+
+use crate::arena::Arena;
+
+// the host mapping holding a region's tables
+enum Backing {
+    Pages(MmapMut),
+    #[cfg(target_os = "linux")]
+    Huge(HugeMap),
+}
+
+// an anonymous mapping backed by 2mb huge pages
+#[cfg(target_os = "linux")]
+struct HugeMap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl HugeMap {
+    fn new(len: usize) -> Result<Self, Error> {
+        let len = (len + 0x1f_ffff) & !0x1f_ffff;
+
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB,
+                -1,
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+}
+
+// the mapping is owned by the HugeMap and never aliased, so it can move and be
+// shared between threads the same way an MmapMut can
+#[cfg(target_os = "linux")]
+unsafe impl Send for HugeMap {}
+#[cfg(target_os = "linux")]
+unsafe impl Sync for HugeMap {}
+
+#[cfg(target_os = "linux")]
+impl Drop for HugeMap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut _, self.len) };
+    }
+}
+
+impl Backing {
+    fn new(len: usize, huge: bool) -> Result<Self, Error> {
+        match huge {
+            #[cfg(target_os = "linux")]
+            true => Ok(Backing::Huge(HugeMap::new(len)?)),
+            #[cfg(not(target_os = "linux"))]
+            true => Err(Error::new(
+                ErrorKind::Unsupported,
+                "huge page backed tables are only supported on linux",
+            )),
+            false => Ok(Backing::Pages(MmapMut::map_anon(len)?)),
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        match self {
+            Backing::Pages(m) => m.as_mut_ptr(),
+            #[cfg(target_os = "linux")]
+            Backing::Huge(m) => m.ptr,
+        }
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        match self {
+            Backing::Pages(m) => m.as_ptr(),
+            #[cfg(target_os = "linux")]
+            Backing::Huge(m) => m.ptr,
+        }
+    }
+}
+
+/// Committed tables held in a single host mapping, which is unmapped when
+/// the region is dropped. The table pages are contiguous in both guest
+/// physical and host memory.
+pub struct TableRegion {
+    root: u64,
+    gpa: u64,
+    pages: usize,
+    backing: Backing,
+}
+
+impl TableRegion {
+    // commit the tables in `arena` from `base`, with `root` the value
+    // pointing at the root table
+    pub(crate) fn commit(arena: &Arena, root: u64, base: u64, huge: bool) -> Result<Self, Error> {
+        let mut r = Self::new(root, base, arena.tables(), huge)?;
+
+        arena.commit_into(base, r.data());
+
+        Ok(r)
+    }
+
+    // an empty region with room for `pages` tables
+    pub(crate) fn new(root: u64, base: u64, pages: usize, huge: bool) -> Result<Self, Error> {
+        Ok(Self {
+            root,
+            gpa: base,
+            pages,
+            backing: Backing::new(pages * 0x1000, huge)?,
+        })
+    }
+
+    // overwrite the table page at index `page`
+    pub(crate) fn write(&mut self, page: usize, table: &[u64; 512]) {
+        self.data()[page * 512..(page + 1) * 512].copy_from_slice(table);
+    }
+
+    fn data(&mut self) -> &mut [u64] {
+        unsafe {
+            slice::from_raw_parts_mut(self.backing.as_mut_ptr() as *mut u64, self.pages * 512)
+        }
+    }
+
+    /// The value to load into CR3, or the EPTP for extended page tables
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// The guest physical address of the first table page
+    pub fn gpa(&self) -> u64 {
+        self.gpa
+    }
+
+    /// The number of table pages
+    pub fn len(&self) -> usize {
+        self.pages
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages == 0
+    }
+
+    /// The guest physical and host virtual address of each table page, to
+    /// map the tables into the guest
+    pub fn pages(&mut self) -> impl Iterator<Item = (u64, *mut u8)> + '_ {
+        let (gpa, hva) = (self.gpa, self.backing.as_mut_ptr());
+
+        (0..self.pages).map(move |ii| {
+            let off = ii * 0x1000;
+            (gpa + off as u64, unsafe { hva.add(off) })
+        })
+    }
+
+    /// The table pages, including any accessed and dirty bits the guest set
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.backing.as_ptr(), self.pages * 0x1000) }
+    }
+
+    /// Read from the tables at a guest physical address, in the form
+    /// `PageTable::from_physical` expects
+    pub fn read(&self, gpa: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let src = gpa.checked_sub(self.gpa).and_then(|off| {
+            let off = off as usize;
+            self.as_slice().get(off..off.checked_add(buf.len())?)
+        });
+
+        match src {
+            Some(src) => {
+                buf.copy_from_slice(src);
+                Ok(())
+            }
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{:#x} is outside of the committed tables", gpa),
+            )),
+        }
+    }
+}
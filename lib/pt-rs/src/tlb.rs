@@ -0,0 +1,170 @@
+//
+// Translation cache
+//
+use crate::{Context, Level, PageFault, PageTable, Prot, PteFlags};
+
+#[derive(Clone, Copy)]
+struct TlbEntry {
+    // the first virtual and physical address of the page
+    vaddr: u64,
+    paddr: u64,
+    p: Prot,
+    ctx: Context,
+    global: bool,
+}
+
+// a direct mapped cache of the translations for one page size
+struct Set {
+    size: u64,
+    entries: Vec<Option<TlbEntry>>,
+}
+
+impl Set {
+    fn new(size: u64, len: usize) -> Self {
+        Self {
+            size,
+            entries: vec![None; len],
+        }
+    }
+
+    // each access type gets its own stretch of the set, so consecutive pages
+    // accessed the same way don't collide
+    fn index(&self, vaddr: u64, p: Prot) -> usize {
+        let len = self.entries.len();
+        let page = (vaddr / self.size) as usize;
+
+        page.wrapping_add(p.bits() as usize * (len / 8)) & (len - 1)
+    }
+
+    fn get(&self, vaddr: u64, p: Prot, ctx: &Context) -> Option<u64> {
+        let base = vaddr & !(self.size - 1);
+
+        match self.entries[self.index(vaddr, p)] {
+            Some(x) if x.vaddr == base && x.p == p && x.ctx == *ctx => {
+                Some(x.paddr + (vaddr - base))
+            }
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, x: TlbEntry) {
+        let idx = self.index(x.vaddr, x.p);
+        self.entries[idx] = Some(x);
+    }
+
+    // drop the page containing vaddr, for every access type
+    fn invalidate(&mut self, vaddr: u64) {
+        let base = vaddr & !(self.size - 1);
+
+        for p in (0..8).map(Prot::from_bits_truncate) {
+            let idx = self.index(vaddr, p);
+
+            if self.entries[idx].is_some_and(|x| x.vaddr == base) {
+                self.entries[idx] = None;
+            }
+        }
+    }
+}
+
+/// A software TLB caching translations by page and access type, along with
+/// the context they were made in. Like the hardware TLB it isn't kept
+/// coherent with the table: `invlpg` drops a changed page, and `flush` drops
+/// everything but global pages, the same as writing CR3.
+pub struct Tlb {
+    // 4kb, 2mb and 1gb pages
+    sets: [Set; 3],
+}
+
+impl Tlb {
+    pub fn new() -> Self {
+        Self {
+            sets: [
+                Set::new(0x1000, 4096),
+                Set::new(0x20_0000, 256),
+                Set::new(0x4000_0000, 16),
+            ],
+        }
+    }
+
+    pub fn translate(&mut self, table: &PageTable, vaddr: u64, p: Prot) -> Option<u64> {
+        self.translate_detailed_with(table, vaddr, p, &Context::default())
+            .ok()
+    }
+
+    pub fn translate_with(
+        &mut self,
+        table: &PageTable,
+        vaddr: u64,
+        p: Prot,
+        ctx: &Context,
+    ) -> Option<u64> {
+        self.translate_detailed_with(table, vaddr, p, ctx).ok()
+    }
+
+    /// Translate a virtual address from the cache, walking `table` and
+    /// caching the result on a miss. Faults aren't cached.
+    pub fn translate_detailed_with(
+        &mut self,
+        table: &PageTable,
+        vaddr: u64,
+        p: Prot,
+        ctx: &Context,
+    ) -> Result<u64, PageFault> {
+        for set in self.sets.iter() {
+            if let Some(paddr) = set.get(vaddr, p, ctx) {
+                return Ok(paddr);
+            }
+        }
+
+        let (paddr, level, e) = table.translate_leaf(vaddr, p, ctx)?;
+
+        let set = match level {
+            Level::Pdpt => &mut self.sets[2],
+            Level::Pd => &mut self.sets[1],
+            _ => &mut self.sets[0],
+        };
+
+        let mask = !(set.size - 1);
+        set.insert(TlbEntry {
+            vaddr: vaddr & mask,
+            paddr: paddr & mask,
+            p,
+            ctx: *ctx,
+            global: e & PteFlags::Global.bits() != 0,
+        });
+
+        Ok(paddr)
+    }
+
+    /// Drop the page containing `vaddr`, whatever its size, including global
+    /// pages
+    pub fn invlpg(&mut self, vaddr: u64) {
+        for set in self.sets.iter_mut() {
+            set.invalidate(vaddr);
+        }
+    }
+
+    /// Drop every page except global ones
+    pub fn flush(&mut self) {
+        for set in self.sets.iter_mut() {
+            for x in set.entries.iter_mut() {
+                if x.is_some_and(|x| !x.global) {
+                    *x = None;
+                }
+            }
+        }
+    }
+
+    /// Drop every page, the same as toggling CR4.PGE
+    pub fn flush_all(&mut self) {
+        for set in self.sets.iter_mut() {
+            set.entries.iter_mut().for_each(|x| *x = None);
+        }
+    }
+}
+
+impl Default for Tlb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
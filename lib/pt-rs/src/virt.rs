@@ -0,0 +1,128 @@
+//
+// Virtual memory access
+//
+use std::fmt;
+use std::io::Error;
+
+use crate::{Context, PageFault, PageTable, Prot};
+
+/// Memory addressed by guest physical address, which virtual accesses are
+/// made against
+pub trait PhysMem {
+    fn read(&self, gpa: u64, buf: &mut [u8]) -> Result<(), Error>;
+    fn write(&mut self, gpa: u64, buf: &[u8]) -> Result<(), Error>;
+}
+
+/// The fault which stopped a virtual memory access
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessFault {
+    /// The first address which couldn't be translated
+    pub vaddr: u64,
+    pub fault: PageFault,
+}
+
+impl fmt::Display for AccessFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} accessing {:#x}", self.fault, self.vaddr)
+    }
+}
+
+impl std::error::Error for AccessFault {}
+
+impl PageTable {
+    pub fn virt_read<M: PhysMem>(
+        &self,
+        mem: &M,
+        vaddr: u64,
+        buf: &mut [u8],
+    ) -> Result<Result<(), AccessFault>, Error> {
+        self.virt_read_with(mem, vaddr, buf, &Context::default())
+    }
+
+    /// Read `buf.len()` bytes from `vaddr`, splitting the access at page
+    /// boundaries. The outer error is a failed read of `mem`, the inner one
+    /// the fault at the first address which isn't readable, in which case
+    /// nothing is read.
+    pub fn virt_read_with<M: PhysMem>(
+        &self,
+        mem: &M,
+        vaddr: u64,
+        buf: &mut [u8],
+        ctx: &Context,
+    ) -> Result<Result<(), AccessFault>, Error> {
+        let chunks = match self.chunks(vaddr, buf.len(), Prot::R, ctx) {
+            Ok(chunks) => chunks,
+            Err(fault) => return Ok(Err(fault)),
+        };
+
+        let mut off = 0;
+
+        for (paddr, len) in chunks {
+            mem.read(paddr, &mut buf[off..off + len])?;
+            off += len;
+        }
+
+        Ok(Ok(()))
+    }
+
+    pub fn virt_write<M: PhysMem>(
+        &self,
+        mem: &mut M,
+        vaddr: u64,
+        buf: &[u8],
+    ) -> Result<Result<(), AccessFault>, Error> {
+        self.virt_write_with(mem, vaddr, buf, &Context::default())
+    }
+
+    /// Write `buf` to `vaddr`, splitting the access at page boundaries. The
+    /// whole range is translated first, so a fault leaves memory untouched.
+    /// Accessed and dirty bits aren't updated.
+    pub fn virt_write_with<M: PhysMem>(
+        &self,
+        mem: &mut M,
+        vaddr: u64,
+        buf: &[u8],
+        ctx: &Context,
+    ) -> Result<Result<(), AccessFault>, Error> {
+        let chunks = match self.chunks(vaddr, buf.len(), Prot::W, ctx) {
+            Ok(chunks) => chunks,
+            Err(fault) => return Ok(Err(fault)),
+        };
+
+        let mut off = 0;
+
+        for (paddr, len) in chunks {
+            mem.write(paddr, &buf[off..off + len])?;
+            off += len;
+        }
+
+        Ok(Ok(()))
+    }
+
+    // translate each page of an access, returning the physical address and
+    // length of the part of the access in it
+    fn chunks(
+        &self,
+        vaddr: u64,
+        len: usize,
+        p: Prot,
+        ctx: &Context,
+    ) -> Result<Vec<(u64, usize)>, AccessFault> {
+        let mut chunks = vec![];
+        let mut done = 0;
+
+        while done < len {
+            let vaddr = vaddr.wrapping_add(done as u64);
+            let size = ((0x1000 - (vaddr & 0xfff)) as usize).min(len - done);
+
+            let paddr = self
+                .translate_detailed_with(vaddr, p, ctx)
+                .map_err(|fault| AccessFault { vaddr, fault })?;
+
+            chunks.push((paddr, size));
+            done += size;
+        }
+
+        Ok(chunks)
+    }
+}
@@ -0,0 +1,121 @@
+extern crate pt;
+
+use pt::{Context, Flags, PageTable, Paging, Prot};
+
+fn state(x: &PageTable) -> Vec<(u64, bool, bool)> {
+    x.mappings()
+        .map(|m| {
+            let accessed = m.flags.contains(Flags::Accessed);
+            let dirty = m.flags.contains(Flags::Dirty);
+
+            (m.vaddr, accessed, dirty)
+        })
+        .collect()
+}
+
+#[test]
+fn mark() {
+    let mut x = PageTable::default();
+    let ctx = Context::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert_large(0x4160_0000, 0x8160_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert_huge(0x8000_0000, 0x4000_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    assert_eq!(
+        x.translate_and_mark(0x4141_1234, Prot::R, &ctx),
+        Ok(0x8181_1234)
+    );
+    assert_eq!(
+        x.translate_and_mark(0x4170_0000, Prot::W, &ctx),
+        Ok(0x8170_0000)
+    );
+    assert_eq!(
+        x.translate_and_mark(0x8000_1000, Prot::R | Prot::W, &ctx),
+        Ok(0x4000_1000)
+    );
+
+    // faulting accesses don't mark anything
+    assert!(x.translate_and_mark(0x4142_0000, Prot::R, &ctx).is_err());
+    assert!(x
+        .translate_and_mark(0x4141_0000, Prot::W, &Context::user())
+        .is_err());
+
+    assert_eq!(
+        state(&x),
+        vec![
+            (0x4141_0000, false, false),
+            (0x4141_1000, true, false),
+            (0x4160_0000, true, true),
+            (0x8000_0000, true, true),
+        ]
+    );
+}
+
+#[test]
+fn tables_stay_accessed() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.translate_and_mark(0x4141_0000, Prot::R, &Context::default())
+        .unwrap();
+
+    // changes below the tables don't clear what the walk marked
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present).unwrap();
+    x.protect(0x4141_0000, 0x2000, Flags::Present).unwrap();
+    x.remove(0x4141_1000).unwrap();
+
+    assert!(!x
+        .pte(0x4141_0000)
+        .unwrap()
+        .flags()
+        .contains(Flags::Accessed));
+    assert!(x.pt(0x4141_0000).unwrap().flags().contains(Flags::Accessed));
+    assert!(x.pd(0x4141_0000).unwrap().flags().contains(Flags::Accessed));
+    assert!(x
+        .pdpt(0x4141_0000)
+        .unwrap()
+        .flags()
+        .contains(Flags::Accessed));
+}
+
+#[test]
+fn readback() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present).unwrap();
+
+    let (cr3, mut tables) = x.commit().unwrap();
+
+    let y = PageTable::from_committed(Paging::Level4, cr3, &tables).unwrap();
+    assert!(state(&y).iter().all(|&(_, a, d)| !a && !d));
+
+    // the guest touches the second 4kb page by setting the bits in its pte
+    let vaddr = 0x4141_1000u64;
+    let mut table = cr3;
+    for shift in [39, 30, 21].iter() {
+        let off = ((vaddr >> shift) as usize & 0x1ff) * 8;
+        let mut e = [0u8; 8];
+        e.copy_from_slice(&tables[&table][off..off + 8]);
+        table = u64::from_le_bytes(e) & 0x000f_ffff_ffff_f000;
+    }
+
+    let off = ((vaddr >> 12) as usize & 0x1ff) * 8;
+    tables.get_mut(&table).unwrap()[off] |= 0x60;
+
+    let y = PageTable::from_committed(Paging::Level4, cr3, &tables).unwrap();
+    assert_eq!(
+        state(&y),
+        vec![(0x4141_0000, false, false), (0x4141_1000, true, true)]
+    );
+
+    tables.clear();
+    assert!(PageTable::from_committed(Paging::Level4, cr3, &tables).is_err());
+}
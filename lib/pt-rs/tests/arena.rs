@@ -0,0 +1,87 @@
+extern crate pt;
+
+use pt::{Flags, Level, PageTable, Prot};
+
+#[test]
+fn freed_tables() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x7fff_0000, 0x8181_1000, Flags::Present).unwrap();
+    assert!(x.remove(0x4141_0000).unwrap());
+
+    // the pd and pt left empty are reused by the next insert
+    x.insert(0x1_0000_0000, 0x8181_2000, Flags::Present)
+        .unwrap();
+
+    let (_, y) = x.commit().unwrap();
+    assert_eq!(y.len(), 6);
+}
+
+#[test]
+fn replaced_tables() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    assert!(x.remove(0x4141_0000).unwrap());
+    x.insert_large(0x4140_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+
+    // the pt left empty is gone, and splitting the 2mb page adds one
+    let (_, y) = x.commit_at(0x10_0000).unwrap();
+    assert_eq!(y.len(), 3);
+
+    let mut x = PageTable::default();
+    x.insert_large(0x4140_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let (_, y) = x.commit_at(0x10_0000).unwrap();
+    assert_eq!(y.len(), 4);
+}
+
+#[test]
+fn translate_after_reuse() {
+    let mut x = PageTable::default();
+    let f = Flags::Present | Flags::Writable;
+
+    for ii in 0..16 {
+        x.insert(ii << 30, 0x1000 * ii, f).unwrap();
+    }
+    for ii in 0..8 {
+        assert!(x.remove(ii << 30).unwrap());
+    }
+    for ii in 16..24 {
+        x.insert(ii << 30, 0x1000 * ii, f).unwrap();
+    }
+
+    for ii in 0..8 {
+        assert_eq!(x.translate(ii << 30, Prot::R), None);
+    }
+    for ii in 8..24 {
+        assert_eq!(x.translate(ii << 30, Prot::W), Some(0x1000 * ii));
+    }
+}
+
+#[test]
+fn entries() {
+    let mut x = PageTable::default();
+    let f = Flags::Present | Flags::Writable | Flags::AttributeTable;
+
+    x.insert_large(0x4140_0000, 0x20_0000, f).unwrap();
+    x.insert(0x8000_0000, 0x1000, Flags::Present).unwrap();
+
+    let pde = x.pt(0x4141_0000).unwrap();
+    assert_eq!(pde.level(), Level::Pd);
+    assert!(pde.is_leaf());
+    assert_eq!(pde.paddr(), Some(0x20_0000));
+    assert_eq!(pde.flags(), f);
+
+    let pdpte = x.pd(0x4141_0000).unwrap();
+    assert!(!pdpte.is_leaf());
+    assert_eq!(pdpte.paddr(), None);
+    assert_eq!(pdpte.flags(), Flags::Present | Flags::Writable);
+
+    assert_eq!(x.pte(0x8000_0000).unwrap().paddr(), Some(0x1000));
+    assert!(x.pml4(0).is_none());
+}
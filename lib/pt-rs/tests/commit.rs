@@ -6,7 +6,7 @@ use pt::{PageTable, Flags};
 fn commit() {
     let mut x = PageTable::default();
 
-    x.insert(0x4141_0000, 0x8181_0000, Flags::Present);
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
 
     let (pml4, y) = x.commit().unwrap();
 
@@ -19,8 +19,8 @@ fn commit() {
 fn commit_one() {
     let mut x = PageTable::default();
 
-    x.insert(0x4141_0000, 0x8181_0000, Flags::Present);
-    x.insert(0x4141_1000, 0x8181_1000, Flags::Present);
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present).unwrap();
 
     let (pml4, y) = x.commit().unwrap();
 
@@ -28,3 +28,45 @@ fn commit_one() {
     assert_eq!(y.len(), 4);
     eprintln!("{:x?}", y);
 }
+
+#[test]
+fn commit_at() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let (pml4, y) = x.commit_at(0x10_0000).unwrap();
+
+    assert_eq!(pml4, 0x10_0000);
+    assert_eq!(
+        y.keys().copied().collect::<Vec<_>>(),
+        vec![0x10_0000, 0x10_1000, 0x10_2000, 0x10_3000]
+    );
+}
+
+#[test]
+fn commit_at_overlap() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x10_3000, Flags::Present).unwrap();
+    assert!(x.commit_at(0x10_0000).is_err());
+
+    let mut x = PageTable::default();
+
+    // the tables end right before the mapped page
+    x.insert(0x4141_0000, 0x10_4000, Flags::Present).unwrap();
+    assert!(x.commit_at(0x10_0000).is_ok());
+
+    let mut x = PageTable::default();
+
+    x.insert_large(0x4140_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+    assert!(x.commit_at(0x3f_f000).is_err());
+}
+
+#[test]
+fn commit_at_unaligned() {
+    let x = PageTable::default();
+
+    assert!(x.commit_at(0x10_0800).is_err());
+}
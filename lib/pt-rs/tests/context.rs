@@ -0,0 +1,143 @@
+extern crate pt;
+
+use pt::{Context, Flags, Level, PageFault, PageTable, PfCode, Prot};
+
+#[test]
+fn user() {
+    let mut x = PageTable::default();
+    let ctx = Context::user();
+
+    // a supervisor page sharing tables with user pages
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert(
+        0x4141_2000,
+        0x8181_2000,
+        Flags::Present | Flags::User | Flags::Writable,
+    )
+    .unwrap();
+
+    assert_eq!(
+        x.translate_detailed_with(0x4141_0000, Prot::R, &ctx),
+        Err(PageFault {
+            code: PfCode::Present | PfCode::User,
+            level: Level::Pt,
+        })
+    );
+    assert_eq!(
+        x.translate_detailed_with(0x4141_1000, Prot::W, &ctx),
+        Err(PageFault {
+            code: PfCode::Present | PfCode::User | PfCode::Write,
+            level: Level::Pt,
+        })
+    );
+    assert_eq!(
+        x.translate_detailed_with(0x4141_3000, Prot::R, &ctx),
+        Err(PageFault {
+            code: PfCode::User,
+            level: Level::Pt,
+        })
+    );
+    assert_eq!(
+        x.translate_with(0x4141_1000, Prot::X, &ctx),
+        Some(0x8181_1000)
+    );
+    assert_eq!(
+        x.translate_with(0x4141_2000, Prot::W, &ctx),
+        Some(0x8181_2000)
+    );
+}
+
+#[test]
+fn supervisor_wp() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::User)
+        .unwrap();
+
+    let mut ctx = Context::supervisor();
+    assert_eq!(x.translate_with(0x4141_1000, Prot::W, &ctx), None);
+
+    ctx.wp = false;
+    assert_eq!(
+        x.translate_with(0x4141_1000, Prot::W, &ctx),
+        Some(0x8181_1000)
+    );
+    assert_eq!(
+        x.translate_with(0x4141_0000, Prot::W, &ctx),
+        Some(0x8181_0000)
+    );
+}
+
+#[test]
+fn smep() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::User)
+        .unwrap();
+
+    let mut ctx = Context::supervisor();
+    assert_eq!(
+        x.translate_with(0x4141_1000, Prot::X, &ctx),
+        Some(0x8181_1000)
+    );
+
+    ctx.smep = true;
+    assert_eq!(
+        x.translate_detailed_with(0x4141_1000, Prot::X, &ctx),
+        Err(PageFault {
+            code: PfCode::Present | PfCode::Fetch,
+            level: Level::Pt,
+        })
+    );
+    assert_eq!(
+        x.translate_with(0x4141_0000, Prot::X, &ctx),
+        Some(0x8181_0000)
+    );
+    assert_eq!(
+        x.translate_with(0x4141_1000, Prot::R, &ctx),
+        Some(0x8181_1000)
+    );
+}
+
+#[test]
+fn smap() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert(
+        0x4141_2000,
+        0x8181_2000,
+        Flags::Present | Flags::User | Flags::Writable,
+    )
+    .unwrap();
+
+    let mut ctx = Context::supervisor();
+    ctx.smap = true;
+
+    assert_eq!(x.translate_with(0x4141_1000, Prot::R, &ctx), None);
+    assert_eq!(x.translate_with(0x4141_2000, Prot::W, &ctx), None);
+    assert_eq!(
+        x.translate_with(0x4141_1000, Prot::X, &ctx),
+        Some(0x8181_1000)
+    );
+    assert_eq!(
+        x.translate_with(0x4141_0000, Prot::R, &ctx),
+        Some(0x8181_0000)
+    );
+
+    ctx.ac = true;
+    assert_eq!(
+        x.translate_with(0x4141_1000, Prot::R, &ctx),
+        Some(0x8181_1000)
+    );
+    assert_eq!(
+        x.translate_with(0x4141_2000, Prot::W, &ctx),
+        Some(0x8181_2000)
+    );
+}
@@ -0,0 +1,157 @@
+extern crate pt;
+
+use pt::{Context, Diff, Flags, Mapping, PageTable, Paging, Prot};
+
+fn mapping(vaddr: u64, paddr: u64, size: u64, flags: Flags) -> Mapping {
+    Mapping {
+        vaddr,
+        paddr,
+        size,
+        flags,
+    }
+}
+
+#[test]
+fn unchanged() {
+    let mut x = PageTable::default();
+
+    x.map_range(0x4141_0000, 0x8181_0000, 0x4000, Flags::Present)
+        .unwrap();
+    x.insert_large(0x4160_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+    assert!(x.diff(&x.clone()).is_empty());
+
+    // the same memory mapped with smaller pages
+    let mut y = x.clone();
+    y.protect(0x4160_0000, 0x1000, Flags::Present).unwrap();
+    assert!(x.diff(&y).is_empty());
+}
+
+#[test]
+fn changes() {
+    let mut x = PageTable::default();
+
+    x.map_range(0x4141_0000, 0x8181_0000, 0x4000, Flags::Present)
+        .unwrap();
+    x.insert_large(0x4160_0000, 0x20_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0xffff_8000_0000_0000, 0x1000, Flags::Present)
+        .unwrap();
+
+    let mut y = x.clone();
+
+    y.remove(0xffff_8000_0000_0000).unwrap();
+    y.map_range(0x5000_0000, 0x9000_0000, 0x2000, Flags::Present)
+        .unwrap();
+    y.insert(0x4141_1000, 0x7000_0000, Flags::Present).unwrap();
+    y.insert(0x4141_2000, 0x7000_1000, Flags::Present).unwrap();
+    y.protect(0x4161_0000, 0x3000, Flags::Present).unwrap();
+
+    assert_eq!(
+        x.diff(&y),
+        Diff {
+            added: vec![mapping(0x5000_0000, 0x9000_0000, 0x2000, Flags::Present)],
+            removed: vec![mapping(
+                0xffff_8000_0000_0000,
+                0x1000,
+                0x1000,
+                Flags::Present
+            )],
+            remapped: vec![(
+                mapping(0x4141_1000, 0x8181_1000, 0x2000, Flags::Present),
+                mapping(0x4141_1000, 0x7000_0000, 0x2000, Flags::Present),
+            )],
+            reprotected: vec![(
+                mapping(
+                    0x4161_0000,
+                    0x21_0000,
+                    0x3000,
+                    Flags::Present | Flags::Writable
+                ),
+                mapping(0x4161_0000, 0x21_0000, 0x3000, Flags::Present),
+            )],
+        }
+    );
+
+    // and the other way around
+    let back = y.diff(&x);
+    assert_eq!(back.added, x.diff(&y).removed);
+    assert_eq!(back.removed, x.diff(&y).added);
+}
+
+#[test]
+fn partial() {
+    let mut x = PageTable::default();
+    let mut y = PageTable::default();
+
+    // a 2mb page replaced by part of it and some other memory
+    x.insert_large(0x4000_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+    y.map_range(0x400f_f000, 0x2f_f000, 0x2000, Flags::Present)
+        .unwrap();
+    y.insert(0x4010_1000, 0x8000_0000, Flags::Present).unwrap();
+
+    let d = x.diff(&y);
+    assert!(d.added.is_empty());
+    assert_eq!(
+        d.removed,
+        vec![
+            mapping(0x4000_0000, 0x20_0000, 0xf_f000, Flags::Present),
+            mapping(0x4010_2000, 0x30_2000, 0xf_e000, Flags::Present),
+        ]
+    );
+    assert_eq!(
+        d.remapped,
+        vec![(
+            mapping(0x4010_1000, 0x30_1000, 0x1000, Flags::Present),
+            mapping(0x4010_1000, 0x8000_0000, 0x1000, Flags::Present),
+        )]
+    );
+}
+
+#[test]
+fn remapped_and_reprotected() {
+    let mut x = PageTable::default();
+    let mut y = PageTable::default();
+
+    x.insert(0x4141_0000, 0x1000, Flags::Present).unwrap();
+    y.insert(0x4141_0000, 0x5000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    // a page which moved and changed flags only counts as remapped
+    let d = x.diff(&y);
+    assert!(d.reprotected.is_empty());
+    assert_eq!(
+        d.remapped,
+        vec![(
+            mapping(0x4141_0000, 0x1000, 0x1000, Flags::Present),
+            mapping(
+                0x4141_0000,
+                0x5000,
+                0x1000,
+                Flags::Present | Flags::Writable
+            ),
+        )]
+    );
+}
+
+#[test]
+fn from_guest() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    let (cr3, tables) = x.clone().commit_at(0x10_0000).unwrap();
+    let mut y = PageTable::from_committed(Paging::Level4, cr3, &tables).unwrap();
+
+    assert!(x.diff(&y).is_empty());
+
+    // accessed and dirty bits don't count as changes
+    y.translate_and_mark(0x4141_0000, Prot::W, &Context::default())
+        .unwrap();
+    assert!(x.diff(&y).is_empty());
+
+    y.protect(0x4141_0000, 0x1000, Flags::Present).unwrap();
+    assert_eq!(x.diff(&y).reprotected.len(), 1);
+}
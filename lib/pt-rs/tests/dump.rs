@@ -0,0 +1,136 @@
+extern crate pt;
+
+use std::io::ErrorKind;
+
+use pt::{Dump, Flags, PageTable, Paging};
+
+#[test]
+fn dump() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert_large(0xffff_8000_0000_0000, 0x20_0000, Flags::Present | Flags::NX)
+        .unwrap();
+
+    let s = x.dump_at(0x10_0000).to_string();
+    let lines: Vec<&str> = s.lines().collect();
+
+    assert_eq!(lines[0], "pml4 @ 0x100000");
+    assert_eq!(lines.len(), 8);
+
+    // every level of the walk, indented under the entry above it
+    assert!(lines[1].starts_with("pml4[000]      0x0000000000101005 P U "));
+    assert!(lines[4].starts_with("      pt[010]  0x0000000081810005 P U "));
+    assert!(lines[4].ends_with("0x0000000041410000-0x0000000041410fff -> 0x81810000"));
+
+    // upper half addresses are sign extended
+    assert!(lines[5].starts_with("pml4[100]"));
+    assert!(lines[7].starts_with("    pd[000]    0x8000000000200081 P PS NX "));
+    assert!(lines[7].ends_with("0xffff800000000000-0xffff8000001fffff -> 0x200000"));
+}
+
+#[test]
+fn committed() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert_large(0xffff_8000_0000_0000, 0x20_0000, Flags::Present | Flags::NX)
+        .unwrap();
+
+    let expected = x.dump_at(0x10_0000).to_string();
+
+    // the committed pages read back the same as the tables they came from
+    let region = x.commit_contiguous(0x10_0000).unwrap();
+    let y = Dump::from_physical(Paging::Level4, 52, region.root(), |gpa, buf| {
+        region.read(gpa, buf)
+    })
+    .unwrap();
+    assert_eq!(y.to_string(), expected);
+
+    let (cr3, tables) = x.commit_at(0x10_0000).unwrap();
+    let y = Dump::from_committed(Paging::Level4, 52, cr3, &tables).unwrap();
+    assert_eq!(y.to_string(), expected);
+}
+
+#[test]
+fn phys_bits() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert_large(0xffff_8000_0000_0000, 0x20_0000, Flags::Present | Flags::NX)
+        .unwrap();
+
+    let (cr3, tables) = x.commit_at(0x10_0000).unwrap();
+
+    // 0x8181_0000 is beyond a 31-bit physical address width
+    let y = Dump::from_committed(Paging::Level4, 31, cr3, &tables).unwrap();
+    assert!(y
+        .explain(0x4141_0000)
+        .to_string()
+        .ends_with("reserved bits set\n"));
+    assert!(!y
+        .explain(0xffff_8000_0000_0000)
+        .to_string()
+        .contains("reserved"));
+
+    let e = Dump::from_committed(Paging::Level4, 53, cr3, &tables)
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn unread() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert_large(0xffff_8000_0000_0000, 0x20_0000, Flags::Present | Flags::NX)
+        .unwrap();
+
+    let (cr3, mut tables) = x.commit_at(0x10_0000).unwrap();
+
+    // drop the pd of the large page
+    let last = *tables.keys().last().unwrap();
+    tables.remove(&last);
+
+    let y = Dump::from_committed(Paging::Level4, 52, cr3, &tables).unwrap();
+    assert!(y
+        .to_string()
+        .contains(&format!("table at {:#x} not read", last)));
+    assert!(y
+        .explain(0xffff_8000_0000_0000)
+        .to_string()
+        .ends_with(&format!("table at {:#x} not read\n", last)));
+}
+
+#[test]
+fn explain() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::User)
+        .unwrap();
+
+    assert_eq!(
+        x.explain(0x4141_0123).to_string(),
+        "0x41410123
+  pml4[000]  @ 0x0: 0x0000000000001005 P U
+  pdpt[001]  @ 0x1008: 0x0000000000002005 P U
+  pd[00a]    @ 0x2050: 0x0000000000003005 P U
+  pt[010]    @ 0x3080: 0x0000000081810005 P U
+  -> 0x81810123 (0x1000 byte page)
+  read: ok
+  write: #PF(0x3) at pml4
+  execute: ok
+"
+    );
+
+    let s = x.explain(0x4141_1000).to_string();
+    assert!(s.contains("  pt[011]    @ 0x3088: 0x0000000000000000\n  not present\n"));
+
+    let s = x.dump().explain(0x8000_0000_0000).to_string();
+    assert_eq!(s, "0x800000000000\n  not canonical\n");
+}
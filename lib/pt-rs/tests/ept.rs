@@ -0,0 +1,273 @@
+extern crate pt;
+
+use std::collections::BTreeMap;
+
+use memmap::MmapMut;
+
+use pt::{EptFlags, EptTable, Error, Level, MemoryType, Prot, EPTP_ACCESSED_DIRTY};
+
+fn read(tables: &BTreeMap<u64, MmapMut>, paddr: u64, idx: usize) -> u64 {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(&tables[&paddr][idx * 8..idx * 8 + 8]);
+    u64::from_le_bytes(b)
+}
+
+#[test]
+fn memory_type() {
+    let f = EptFlags::Read;
+
+    assert_eq!(
+        f.with_memory_type(MemoryType::WriteCombining)
+            .unwrap()
+            .memory_type(),
+        Some(MemoryType::WriteCombining)
+    );
+    assert_eq!(f.with_memory_type(MemoryType::UncacheableMinus), None);
+    assert_eq!(f.memory_type(), Some(MemoryType::Uncacheable));
+    assert_eq!((f | EptFlags::MemoryType).memory_type(), None);
+}
+
+#[test]
+fn translate() {
+    let mut x = EptTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, EptFlags::Read | EptFlags::Write)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, EptFlags::Read | EptFlags::Execute)
+        .unwrap();
+    x.insert_large(0x4160_0000, 0x1_8160_0000, EptFlags::Read | EptFlags::Write)
+        .unwrap();
+    x.insert_huge(0x8000_0000, 0x2_4000_0000, EptFlags::Execute)
+        .unwrap();
+
+    assert_eq!(
+        x.translate(0x4141_0123, Prot::R | Prot::W),
+        Some(0x8181_0123)
+    );
+    assert_eq!(x.translate(0x4141_0123, Prot::X), None);
+    assert_eq!(x.translate(0x4141_1000, Prot::X), Some(0x8181_1000));
+    assert_eq!(x.translate(0x4141_1000, Prot::W), None);
+    assert_eq!(x.translate(0x4141_2000, Prot::R), None);
+
+    assert_eq!(x.translate(0x4170_0000, Prot::W), Some(0x1_8170_0000));
+
+    // execute only
+    assert_eq!(x.translate(0x8123_4567, Prot::X), Some(0x2_4123_4567));
+    assert_eq!(x.translate(0x8123_4567, Prot::R), None);
+
+    assert_eq!(x.translate(1 << 48, Prot::R), None);
+}
+
+#[test]
+fn user_execute() {
+    let mut x = EptTable::default();
+
+    x.insert(0x1000, 0x2000, EptFlags::Read | EptFlags::UserExecute)
+        .unwrap();
+    x.insert(0x3000, 0x4000, EptFlags::Read | EptFlags::Execute)
+        .unwrap();
+
+    // without mode-based execute control both modes need execute
+    assert_eq!(x.translate_user(0x1000, Prot::X), None);
+    assert_eq!(x.translate_user(0x3000, Prot::X), Some(0x4000));
+
+    x.set_mode_based_execute(true);
+    assert_eq!(x.translate(0x1000, Prot::X), None);
+    assert_eq!(x.translate_user(0x1000, Prot::X), Some(0x2000));
+    assert_eq!(x.translate(0x3000, Prot::X), Some(0x4000));
+    assert_eq!(x.translate_user(0x3000, Prot::X), None);
+
+    // and user execute alone only makes an entry present with it
+    let mut x = EptTable::default();
+    x.insert(0x1000, 0x2000, EptFlags::UserExecute).unwrap();
+    assert!(!x.translate_detailed(0x1000, Prot::R).unwrap_err().present);
+
+    x.set_mode_based_execute(true);
+    assert!(x.translate_detailed(0x1000, Prot::R).unwrap_err().present);
+}
+
+#[test]
+fn misconfigured() {
+    let mut x = EptTable::default();
+
+    x.insert(0x1000, 0x2000, EptFlags::Write).unwrap();
+    assert_eq!(x.translate(0x1000, Prot::W), None);
+}
+
+#[test]
+fn replace() {
+    let mut x = EptTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, EptFlags::Read).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, EptFlags::Read).unwrap();
+    x.insert_large(0x4160_0000, 0x1_8160_0000, EptFlags::Read | EptFlags::Write)
+        .unwrap();
+    x.insert_huge(0x8000_0000, 0x2_4000_0000, EptFlags::Execute)
+        .unwrap();
+
+    // a 4kb page inside of the 2mb page keeps the rest of it
+    x.insert(0x4160_0000, 0x9000_0000, EptFlags::Read).unwrap();
+    assert_eq!(x.translate(0x4160_0000, Prot::R), Some(0x9000_0000));
+    assert_eq!(x.translate(0x4160_1000, Prot::R), Some(0x1_8160_1000));
+    assert_eq!(x.translate(0x4160_1000, Prot::W), Some(0x1_8160_1000));
+
+    // and a 1gb page can't replace everything below it
+    assert!(matches!(
+        x.insert_huge(0x4000_0000, 0x4000_0000, EptFlags::Read),
+        Err(Error::Overlap(0x4141_0000))
+    ));
+    assert_eq!(x.translate(0x4141_1000, Prot::R), Some(0x8181_1000));
+
+    // a 2mb page inside of the 1gb page splits it
+    x.insert_large(0x8020_0000, 0x20_0000, EptFlags::Read)
+        .unwrap();
+    assert_eq!(x.translate(0x8020_0000, Prot::R), Some(0x20_0000));
+    assert_eq!(x.translate(0x8000_0000, Prot::X), Some(0x2_4000_0000));
+    assert_eq!(x.translate(0x8040_0000, Prot::R), None);
+}
+
+#[test]
+fn map_range() {
+    let mut x = EptTable::default();
+
+    x.map_range(0x3fe0_0000, 0x3fe0_0000, 0x4040_1000, EptFlags::Read)
+        .unwrap();
+    assert_eq!(x.translate(0x3fe0_0000, Prot::R), Some(0x3fe0_0000));
+    assert_eq!(x.translate(0x8020_0fff, Prot::R), Some(0x8020_0fff));
+    assert_eq!(x.translate(0x8020_1000, Prot::R), None);
+
+    assert!(matches!(
+        x.map_range(0x8020_0000, 0, 0x1000, EptFlags::Read),
+        Err(Error::Overlap(0x8020_0000))
+    ));
+    assert!(matches!(
+        x.map_range(0x9000_0000, 0x10, 0x1000, EptFlags::Read),
+        Err(Error::Misaligned(0x10))
+    ));
+}
+
+#[test]
+fn commit() {
+    let mut x = EptTable::default();
+    let wb = |f: EptFlags| f.with_memory_type(MemoryType::WriteBack).unwrap();
+
+    x.insert(
+        0x4141_0000,
+        0x8181_0000,
+        wb(EptFlags::Read | EptFlags::Write),
+    )
+    .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, EptFlags::Read | EptFlags::Execute)
+        .unwrap();
+    x.insert_large(
+        0x4160_0000,
+        0x1_8160_0000,
+        wb(EptFlags::Read | EptFlags::Write | EptFlags::IgnorePat),
+    )
+    .unwrap();
+    x.insert_huge(0x8000_0000, 0x2_4000_0000, EptFlags::Execute)
+        .unwrap();
+
+    let (eptp, tables) = x.commit().unwrap();
+
+    // write back, 4-level, accessed and dirty flags disabled
+    assert_eq!(eptp & 0xfff, 0x1e);
+    assert_eq!(eptp & EPTP_ACCESSED_DIRTY, 0);
+    assert_eq!(tables.len(), 4);
+
+    let pml4 = eptp & !0xfff;
+    let pml4e = read(&tables, pml4, 0);
+    assert_eq!(pml4e & 0xfff, 0b111);
+
+    let pdpt = pml4e & !0xfff;
+    let pdpte = read(&tables, pdpt, 1);
+    assert_eq!(pdpte & 0xfff, 0b111);
+
+    // the 1gb page is execute only, with the page size bit
+    assert_eq!(read(&tables, pdpt, 2), 0x2_4000_0000 | 0x80 | 0b100);
+
+    let pd = pdpte & !0xfff;
+    let pde = read(&tables, pd, 0xb);
+    assert_eq!(pde, 0x1_8160_0000 | 0x80 | 0x40 | (6 << 3) | 0b11);
+
+    let pt = read(&tables, pd, 0xa) & !0xfff;
+    assert_eq!(read(&tables, pt, 0x10), 0x8181_0000 | (6 << 3) | 0b11);
+    assert_eq!(read(&tables, pt, 0x11), 0x8181_1000 | 0b101);
+}
+
+#[test]
+fn commit_at_overlap() {
+    let mut x = EptTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, EptFlags::Read).unwrap();
+
+    assert!(x.commit_contiguous(0x8181_0000).is_err());
+    assert!(x.commit_at(0x1000).is_ok());
+}
+
+#[test]
+fn translate_detailed() {
+    let mut x = EptTable::default();
+
+    // the tables above allow execute for the page next to it
+    x.insert(0x4141_0000, 0x8181_0000, EptFlags::Read | EptFlags::Write)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, EptFlags::Read | EptFlags::Execute)
+        .unwrap();
+    x.insert_huge(0x8000_0000, 0x2_4000_0000, EptFlags::Execute)
+        .unwrap();
+
+    let v = x.translate_detailed(0x4141_0000, Prot::X).unwrap_err();
+    assert_eq!(
+        (v.level, v.present, v.misconfigured),
+        (Level::Pt, true, false)
+    );
+
+    let v = x.translate_detailed(0x4180_0000, Prot::R).unwrap_err();
+    assert_eq!((v.level, v.present), (Level::Pd, false));
+
+    let v = x.translate_detailed(0x8000_0000, Prot::R).unwrap_err();
+    assert_eq!((v.level, v.present), (Level::Pdpt, true));
+
+    // memory type 2 is reserved
+    x.insert(
+        0x1000,
+        0x1000,
+        EptFlags::Read | EptFlags::from_bits_truncate(2 << 3),
+    )
+    .unwrap();
+    let v = x.translate_detailed(0x1000, Prot::R).unwrap_err();
+    assert_eq!((v.level, v.misconfigured), (Level::Pt, true));
+    assert_eq!(v.to_string(), "EPT misconfiguration at 0x1000 in pt");
+}
+
+#[test]
+fn invalid() {
+    let mut x = EptTable::default();
+
+    assert!(matches!(
+        x.insert(0x1234, 0x5678_9000, EptFlags::Read),
+        Err(Error::Misaligned(0x1234))
+    ));
+    assert!(matches!(
+        x.insert_large(0x20_0000, 0x5678_9000, EptFlags::Read),
+        Err(Error::Misaligned(0x5678_9000))
+    ));
+
+    // a 4-level EPT only translates 48 bits of guest physical address
+    assert!(matches!(
+        x.map_range(1 << 48, 0x1000_0000, 0x1000, EptFlags::Read),
+        Err(Error::PhysicalAddress(0x1_0000_0000_0000))
+    ));
+    assert!(matches!(
+        x.map_range((1 << 48) - 0x1000, 0x1000_0000, 0x2000, EptFlags::Read),
+        Err(Error::PhysicalAddress(_))
+    ));
+    assert!(matches!(
+        x.insert_huge(1 << 48, 0, EptFlags::Read),
+        Err(Error::PhysicalAddress(_))
+    ));
+
+    assert_eq!(x.translate(0, Prot::R), None);
+    assert_eq!(x.translate(0x1000, Prot::R), None);
+}
@@ -0,0 +1,133 @@
+extern crate pt;
+
+use pt::{Error, Flags, PageTable, PageTable32, PageTablePae, Paging, Prot};
+
+#[test]
+fn misaligned() {
+    let mut x = PageTable::default();
+
+    assert!(matches!(
+        x.insert(0x4141_0123, 0x8181_0000, Flags::Present),
+        Err(Error::Misaligned(0x4141_0123))
+    ));
+    assert!(matches!(
+        x.insert(0x4141_0000, 0x8181_0123, Flags::Present),
+        Err(Error::Misaligned(0x8181_0123))
+    ));
+    assert!(matches!(
+        x.insert_large(0x4140_0000, 0x8181_0000, Flags::Present),
+        Err(Error::Misaligned(0x8181_0000))
+    ));
+    assert!(matches!(
+        x.insert_huge(0x4000_1000, 0x8000_0000, Flags::Present),
+        Err(Error::Misaligned(0x4000_1000))
+    ));
+
+    // nothing was mapped
+    assert_eq!(x.mappings().count(), 0);
+}
+
+#[test]
+fn non_canonical() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    // would alias 0x4141_0000 if the upper bits were dropped
+    let alias = 0x0001_0000_4141_0000;
+
+    assert!(matches!(
+        x.insert(alias, 0x8181_1000, Flags::Present),
+        Err(Error::NonCanonical(_))
+    ));
+    assert!(matches!(x.remove(alias), Err(Error::NonCanonical(_))));
+    assert!(matches!(
+        x.protect(alias, 0x1000, Flags::empty()),
+        Err(Error::NonCanonical(_))
+    ));
+    assert_eq!(x.translate(0x4141_0000, Prot::R), Some(0x8181_0000));
+
+    // ranges can't run off the end of the lower half
+    assert!(matches!(
+        x.map_range(0x7fff_ffff_f000, 0x1000, 0x2000, Flags::Present),
+        Err(Error::NonCanonical(0x7fff_ffff_f000))
+    ));
+
+    // but the upper half is fine with 5-level paging
+    let mut y = PageTable::new(Paging::Level5);
+    y.insert(alias, 0x8181_1000, Flags::Present).unwrap();
+}
+
+#[test]
+fn physical_address() {
+    let mut x = PageTable::default();
+
+    assert!(matches!(
+        x.insert(0x4141_0000, 0x10_0000_0000_0000, Flags::Present),
+        Err(Error::PhysicalAddress(0x10_0000_0000_0000))
+    ));
+    assert!(matches!(
+        x.map_range(0x4141_0000, 0xf_ffff_ffff_f000, 0x2000, Flags::Present),
+        Err(Error::PhysicalAddress(0xf_ffff_ffff_f000))
+    ));
+
+    x.insert(0x4141_0000, 0xf_ffff_ffff_f000, Flags::Present)
+        .unwrap();
+}
+
+#[test]
+fn commit() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    assert!(matches!(
+        x.commit_contiguous(0x800),
+        Err(Error::Misaligned(0x800))
+    ));
+    assert!(matches!(
+        x.commit_contiguous(0x8180_e000),
+        Err(Error::TableOverlap(0x8181_0000))
+    ));
+    assert!(matches!(
+        x.commit_live(0x10_0000, 2),
+        Err(Error::Capacity(4))
+    ));
+}
+
+#[test]
+fn legacy() {
+    let mut x = PageTable32::default();
+
+    assert!(matches!(
+        x.insert(0x4141_0123, 0x8181_0000, Flags::Present),
+        Err(Error::Misaligned(0x4141_0123))
+    ));
+    assert!(matches!(
+        x.insert_large(0x40_0000, 0x1_0000_0000_1234, Flags::Present),
+        Err(Error::Misaligned(0x1_0000_0000_1234))
+    ));
+
+    // PSE-36 only holds 40 bits of physical address
+    assert!(matches!(
+        x.insert_large(0x40_0000, 0x100_0000_0000, Flags::Present),
+        Err(Error::PhysicalAddress(0x100_0000_0000))
+    ));
+    assert_eq!(x.translate(0x40_0000, Prot::R), None);
+
+    let mut x = PageTablePae::default();
+
+    assert!(matches!(
+        x.insert(0x4141_0000, 0x8181_0800, Flags::Present),
+        Err(Error::Misaligned(0x8181_0800))
+    ));
+    assert!(matches!(
+        x.insert_large(0x4141_0000, 0x8160_0000, Flags::Present),
+        Err(Error::Misaligned(0x4141_0000))
+    ));
+    assert!(matches!(
+        x.insert(0x4141_0000, 1 << 52, Flags::Present),
+        Err(Error::PhysicalAddress(_))
+    ));
+    assert_eq!(x.translate(0x4141_0000, Prot::R), None);
+}
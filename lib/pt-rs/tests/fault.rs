@@ -0,0 +1,74 @@
+extern crate pt;
+
+use pt::{Flags, Level, PageFault, PageTable, Paging, PfCode, Prot};
+
+#[test]
+fn not_present() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let fault = |code, level| Err(PageFault { code, level });
+
+    assert_eq!(x.translate_detailed(0x4141_0000, Prot::R), Ok(0x8181_0000));
+    assert_eq!(
+        x.translate_detailed(0x4141_1000, Prot::R),
+        fault(PfCode::empty(), Level::Pt)
+    );
+    assert_eq!(
+        x.translate_detailed(0x4160_0000, Prot::W),
+        fault(PfCode::Write, Level::Pd)
+    );
+    assert_eq!(
+        x.translate_detailed(0x8000_0000, Prot::X),
+        fault(PfCode::Fetch, Level::Pdpt)
+    );
+    assert_eq!(
+        x.translate_detailed(0x80_0000_0000, Prot::R),
+        fault(PfCode::empty(), Level::Pml4)
+    );
+    assert_eq!(
+        x.translate_detailed(0x1_0000_4141_0000, Prot::R),
+        fault(PfCode::empty(), Level::Pml4)
+    );
+
+    let y = PageTable::new(Paging::Level5);
+    assert_eq!(
+        y.translate_detailed(0x4141_0000, Prot::R),
+        fault(PfCode::empty(), Level::Pml5)
+    );
+}
+
+#[test]
+fn protection() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::NX)
+        .unwrap();
+    x.insert_large(0x4160_0000, 0x8160_0000, Flags::Present)
+        .unwrap();
+
+    let fault = |code, level| Err(PageFault { code, level });
+
+    assert_eq!(
+        x.translate_detailed(0x4141_0000, Prot::W),
+        fault(PfCode::Present | PfCode::Write, Level::Pml4)
+    );
+    assert_eq!(
+        x.translate_detailed(0x4141_0000, Prot::X),
+        fault(PfCode::Present | PfCode::Fetch, Level::Pd)
+    );
+    assert_eq!(
+        x.translate_detailed(0x4160_0000, Prot::W),
+        fault(PfCode::Present | PfCode::Write, Level::Pml4)
+    );
+
+    // a writable sibling makes the tables writable, so the leaf is the one
+    // that faults
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::Writable)
+        .unwrap();
+    assert_eq!(
+        x.translate_detailed(0x4141_0000, Prot::W),
+        fault(PfCode::Present | PfCode::Write, Level::Pt)
+    );
+}
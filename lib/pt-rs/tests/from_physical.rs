@@ -0,0 +1,100 @@
+extern crate pt;
+
+use pt::{Flags, Mapping, PageTable, Paging, Prot};
+
+#[test]
+fn round_trip() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert(
+        0x4141_1000,
+        0x8181_1000,
+        Flags::Present | Flags::Writable | Flags::NX,
+    )
+    .unwrap();
+    x.insert_large(0x4160_0000, 0x8160_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert_huge(
+        0xffff_8000_0000_0000,
+        0x4000_0000,
+        Flags::Present | Flags::NX,
+    )
+    .unwrap();
+
+    let expected: Vec<Mapping> = x.mappings().collect();
+
+    let region = x.commit_contiguous(0x10_0000).unwrap();
+    let y = PageTable::from_physical(region.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+
+    assert_eq!(y.mappings().collect::<Vec<_>>(), expected);
+    assert_eq!(y.translate(0x4141_1234, Prot::W).unwrap(), 0x8181_1234);
+    assert_eq!(y.translate(0x4141_1234, Prot::X), None);
+
+    // and it can be committed again to the same layout
+    let again = y.commit_contiguous(0x10_0000).unwrap();
+    assert_eq!(again.as_slice(), region.as_slice());
+}
+
+#[test]
+fn round_trip_la57() {
+    let mut x = PageTable::new(Paging::Level5);
+    x.insert(0x1_4141_4141_4000, 0x8181_0000, Flags::Present)
+        .unwrap();
+    x.insert(
+        0xff41_4141_4141_4000,
+        0x1234_0000,
+        Flags::Present | Flags::Writable,
+    )
+    .unwrap();
+
+    let expected: Vec<Mapping> = x.mappings().collect();
+
+    let region = x.commit_contiguous(0).unwrap();
+    let y = PageTable::from_physical_la57(region.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+
+    assert_eq!(y.paging(), Paging::Level5);
+    assert_eq!(y.mappings().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn non_present() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert_large(0x4160_0000, 0x8160_0000, Flags::Present)
+        .unwrap();
+
+    let (cr3, mut tables) = x.commit().unwrap();
+
+    // clear the present bit on the pde for the 2mb page, leaving the rest of
+    // the entry behind
+    let pd = tables
+        .iter_mut()
+        .find(|(_, page)| page[0xb * 8] & 0x80 != 0)
+        .unwrap()
+        .1;
+    pd[0xb * 8] &= !1;
+
+    let x = PageTable::from_committed(Paging::Level4, cr3, &tables).unwrap();
+
+    assert_eq!(x.translate(0x4160_0000, Prot::R), None);
+    assert_eq!(x.translate(0x4141_0000, Prot::R).unwrap(), 0x8181_0000);
+    assert_eq!(x.mappings().count(), 1);
+}
+
+#[test]
+fn read_error() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let (cr3, mut tables) = x.commit().unwrap();
+
+    // drop a table page, so the walk references memory that doesn't exist
+    let last = *tables.keys().last().unwrap();
+    tables.remove(&last);
+
+    assert!(PageTable::from_committed(Paging::Level4, cr3, &tables).is_err());
+}
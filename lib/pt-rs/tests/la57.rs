@@ -0,0 +1,76 @@
+extern crate pt;
+
+use pt::{Flags, PageTable, Paging, Prot};
+
+#[test]
+fn translate_la57() {
+    let mut x = PageTable::new(Paging::Level5);
+
+    x.insert(0x1_4141_4141_4000, 0x8181_0000, Flags::Present)
+        .unwrap();
+    x.insert(
+        0xff41_4141_4141_4000,
+        0x1234_0000,
+        Flags::Present | Flags::Writable,
+    )
+    .unwrap();
+
+    assert_eq!(
+        x.translate(0x1_4141_4141_4123, Prot::R).unwrap(),
+        0x8181_0123
+    );
+    assert_eq!(x.translate(0x1_4141_4141_4123, Prot::W), None);
+    assert_eq!(
+        x.translate(0xff41_4141_4141_4000, Prot::W).unwrap(),
+        0x1234_0000
+    );
+
+    // same low 48 bits, different pml5 entry
+    assert_eq!(x.translate(0x0141_4141_4000, Prot::R), None);
+}
+
+#[test]
+fn canonical() {
+    let x = PageTable::new(Paging::Level4);
+    let y = PageTable::new(Paging::Level5);
+
+    assert!(x.is_canonical(0x7fff_ffff_f000));
+    assert!(x.is_canonical(0xffff_8000_0000_0000));
+    assert!(!x.is_canonical(0x8000_0000_0000));
+    assert!(!x.is_canonical(0x1_4141_4141_4000));
+
+    assert!(y.is_canonical(0x8000_0000_0000));
+    assert!(y.is_canonical(0x00ff_ffff_ffff_f000));
+    assert!(y.is_canonical(0xff00_0000_0000_0000));
+    assert!(!y.is_canonical(0x0100_0000_0000_0000));
+}
+
+#[test]
+fn translate_non_canonical() {
+    let mut x = PageTable::default();
+
+    // only the low 48 bits are used to index the tables, so this aliases
+    // 0x4141_4000, but it must not translate
+    x.insert(0x4141_4000, 0, Flags::Present).unwrap();
+    assert_eq!(x.translate(0x4141_4000, Prot::R).unwrap(), 0);
+    assert_eq!(x.translate(0x1_0000_4141_4000, Prot::R), None);
+}
+
+#[test]
+fn commit_la57() {
+    let mut x = PageTable::new(Paging::Level5);
+
+    x.insert(0x1_4141_4141_4000, 0x8181_0000, Flags::Present)
+        .unwrap();
+
+    let (pml5, y) = x.commit().unwrap();
+
+    // pml5, pml4, pdpt, pd, pt
+    assert_eq!(pml5, 0);
+    assert_eq!(y.len(), 5);
+
+    let pml5e = u64::from_le_bytes([
+        y[&0][8], y[&0][9], y[&0][10], y[&0][11], y[&0][12], y[&0][13], y[&0][14], y[&0][15],
+    ]);
+    assert_eq!(pml5e, 0x1000 | 0x1);
+}
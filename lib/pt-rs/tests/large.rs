@@ -0,0 +1,97 @@
+extern crate pt;
+
+use pt::{Error, Flags, PageTable, Prot};
+
+#[test]
+fn translate_large() {
+    let mut x = PageTable::default();
+
+    x.insert_large(0x4140_0000, 0x8180_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    assert_eq!(x.translate(0x4140_0000, Prot::R).unwrap(), 0x8180_0000);
+    assert_eq!(x.translate(0x4141_4141, Prot::W).unwrap(), 0x8181_4141);
+    assert_eq!(x.translate(0x415f_ffff, Prot::X).unwrap(), 0x819f_ffff);
+    assert_eq!(x.translate(0x4160_0000, Prot::R), None);
+    assert!(x.pte(0x4140_0000).is_none());
+}
+
+#[test]
+fn translate_huge() {
+    let mut x = PageTable::default();
+
+    x.insert_huge(0x4000_0000, 0x8000_0000, Flags::NX | Flags::Present)
+        .unwrap();
+
+    assert_eq!(x.translate(0x4000_0000, Prot::R).unwrap(), 0x8000_0000);
+    assert_eq!(x.translate(0x7fff_ffff, Prot::R).unwrap(), 0xbfff_ffff);
+    assert_eq!(x.translate(0x4141_4141, Prot::W), None);
+    assert_eq!(x.translate(0x4141_4141, Prot::X), None);
+    assert_eq!(x.translate(0x8000_0000, Prot::R), None);
+    assert!(x.pt(0x4000_0000).is_none());
+}
+
+#[test]
+fn small_splits_large() {
+    let mut x = PageTable::default();
+
+    x.insert_large(0x4140_0000, 0x8180_0000, Flags::Present)
+        .unwrap();
+    x.insert(0x4141_0000, 0x1234_0000, Flags::Present).unwrap();
+
+    // the rest of the 2mb page stays mapped
+    assert_eq!(x.translate(0x4141_0000, Prot::R).unwrap(), 0x1234_0000);
+    assert_eq!(x.translate(0x4140_0000, Prot::R).unwrap(), 0x8180_0000);
+    assert_eq!(x.translate(0x415f_f123, Prot::R).unwrap(), 0x819f_f123);
+
+    // and a large page can't replace the smaller ones
+    assert!(matches!(
+        x.insert_large(0x4140_0000, 0x8180_0000, Flags::Present),
+        Err(Error::Overlap(0x4140_0000))
+    ));
+    assert!(matches!(
+        x.insert_huge(0x4000_0000, 0x4000_0000, Flags::Present),
+        Err(Error::Overlap(0x4140_0000))
+    ));
+
+    // a 2mb page inside of a 1gb page splits it too
+    x.insert_huge(0x8000_0000, 0x1_0000_0000, Flags::Present)
+        .unwrap();
+    x.insert_large(0x8020_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+    assert_eq!(x.translate(0x8000_0000, Prot::R).unwrap(), 0x1_0000_0000);
+    assert_eq!(x.translate(0x8020_0000, Prot::R).unwrap(), 0x20_0000);
+    assert_eq!(x.translate(0xbfff_ffff, Prot::R).unwrap(), 0x1_3fff_ffff);
+}
+
+#[test]
+fn commit_large() {
+    let mut x = PageTable::default();
+
+    x.insert_large(0x4140_0000, 0x8180_0000, Flags::Present)
+        .unwrap();
+    x.insert_huge(
+        0x80_0000_0000,
+        0x4000_0000,
+        Flags::Present | Flags::Writable,
+    )
+    .unwrap();
+
+    let (pml4, y) = x.commit().unwrap();
+
+    // pml4 + pdpt + pd for the 2mb page, and a second pdpt for the 1gb page
+    assert_eq!(pml4, 0);
+    assert_eq!(y.len(), 4);
+
+    let entries: Vec<u64> = y
+        .values()
+        .flat_map(|page| {
+            page.chunks(8)
+                .map(|e| u64::from_le_bytes([e[0], e[1], e[2], e[3], e[4], e[5], e[6], e[7]]))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    assert!(entries.contains(&(0x8180_0000 | 0x80 | 0x1)));
+    assert!(entries.contains(&(0x4000_0000 | 0x80 | 0x2 | 0x1)));
+}
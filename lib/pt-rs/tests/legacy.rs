@@ -0,0 +1,202 @@
+extern crate pt;
+
+use pt::{Error, Flags, PageTable32, PageTablePae, Prot};
+
+fn entry32(page: &[u8], idx: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&page[idx * 4..idx * 4 + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn entry64(page: &[u8], idx: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&page[idx * 8..idx * 8 + 8]);
+    u64::from_le_bytes(b)
+}
+
+#[test]
+fn translate_32() {
+    let mut x = PageTable32::default();
+
+    x.insert(0x4141_4000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert_large(
+        0x8000_0000,
+        0x12_0040_0000,
+        Flags::Present | Flags::Writable,
+    )
+    .unwrap();
+
+    assert_eq!(x.translate(0x4141_4123, Prot::R).unwrap(), 0x8181_0123);
+    assert_eq!(x.translate(0x4141_4123, Prot::W), None);
+    assert_eq!(x.translate(0x4141_5000, Prot::R), None);
+    assert_eq!(x.translate(0x8012_3456, Prot::W).unwrap(), 0x12_0052_3456);
+    assert_eq!(x.translate(0x8040_0000, Prot::R), None);
+}
+
+#[test]
+fn commit_32() {
+    let mut x = PageTable32::default();
+
+    x.insert(0x4141_4000, 0x8181_0000, Flags::Present | Flags::User)
+        .unwrap();
+    x.insert_large(0x8000_0000, 0x12_0040_0000, Flags::Present)
+        .unwrap();
+
+    let (cr3, y) = x.commit().unwrap();
+
+    assert_eq!(cr3, 0);
+    assert_eq!(y.len(), 2);
+
+    assert_eq!(entry32(&y[&0], 0x4141_4000 >> 22), 0x1000 | 0x5);
+    assert_eq!(entry32(&y[&0], 0x200), 0x0040_0000 | (0x12 << 13) | 0x81);
+    assert_eq!(entry32(&y[&0x1000], 0x14), 0x8181_0000 | 0x5);
+}
+
+#[test]
+fn translate_pae() {
+    let mut x = PageTablePae::default();
+
+    x.insert(0x4141_4000, 0x1_8181_0000, Flags::Present | Flags::NX)
+        .unwrap();
+    x.insert_large(0xc000_0000, 0x4000_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    assert_eq!(x.translate(0x4141_4123, Prot::R).unwrap(), 0x1_8181_0123);
+    assert_eq!(x.translate(0x4141_4123, Prot::X), None);
+    assert_eq!(x.translate(0x4141_4123, Prot::W), None);
+    assert_eq!(x.translate(0xc01f_ffff, Prot::X).unwrap(), 0x401f_ffff);
+    assert_eq!(x.translate(0xc020_0000, Prot::R), None);
+}
+
+#[test]
+fn commit_pae() {
+    let mut x = PageTablePae::default();
+
+    x.insert(0x4141_4000, 0x1_8181_0000, Flags::Present | Flags::NX)
+        .unwrap();
+    x.insert_large(0xc000_0000, 0x4000_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    let (cr3, y) = x.commit().unwrap();
+
+    // pdpt, pd and pt for the first mapping, and a pd for the second
+    assert_eq!(cr3, 0);
+    assert_eq!(y.len(), 4);
+
+    // pdptes must only have the present bit set
+    assert_eq!(entry64(&y[&0], 1), 0x1000 | 0x1);
+    assert_eq!(entry64(&y[&0], 3), 0x3000 | 0x1);
+
+    assert_eq!(entry64(&y[&0x1000], 0xa), 0x2000 | 0x1);
+    assert_eq!(entry64(&y[&0x2000], 0x14), 0x8000_0001_8181_0001);
+    assert_eq!(entry64(&y[&0x3000], 0), 0x4000_0000 | 0x83);
+}
+
+#[test]
+fn commit_at_legacy() {
+    let mut x = PageTable32::default();
+
+    x.insert(0x4141_4000, 0x8181_0000, Flags::Present).unwrap();
+
+    let (cr3, y) = x.commit_at(0x8000).unwrap();
+
+    assert_eq!(cr3, 0x8000);
+    assert_eq!(entry32(&y[&0x8000], 0x4141_4000 >> 22), 0x9000 | 0x1);
+
+    let mut x = PageTablePae::default();
+
+    x.insert_large(0xc000_0000, 0x4000_0000, Flags::Present)
+        .unwrap();
+    assert!(x.commit_at(0x401f_f000).is_err());
+
+    let x = PageTablePae::default();
+    assert!(x.commit_at(0x1_0000_0000).is_err());
+}
+
+#[test]
+fn overlap_legacy() {
+    let mut x = PageTable32::default();
+
+    x.insert_large(0x8000_0000, 0x12_0040_0000, Flags::Present)
+        .unwrap();
+    x.insert(0x4141_4000, 0x8181_0000, Flags::Present).unwrap();
+
+    // a pte can't hold the rest of a 4mb page above 4gb, and a 4mb page
+    // doesn't replace 4kb pages
+    assert!(matches!(
+        x.insert(0x8000_1000, 0x1000, Flags::Present),
+        Err(Error::Overlap(0x8000_0000))
+    ));
+    assert!(matches!(
+        x.insert_large(0x4140_0000, 0x40_0000, Flags::Present),
+        Err(Error::Overlap(0x4141_4000))
+    ));
+    assert_eq!(x.translate(0x8000_1000, Prot::R).unwrap(), 0x12_0040_1000);
+    assert_eq!(x.translate(0x4141_4000, Prot::R).unwrap(), 0x8181_0000);
+
+    let mut x = PageTablePae::default();
+
+    x.insert_large(0xc000_0000, 0x4000_0000, Flags::Present)
+        .unwrap();
+    x.insert(0x4141_4000, 0x1_8181_0000, Flags::Present)
+        .unwrap();
+
+    assert!(matches!(
+        x.insert_large(0x4140_0000, 0x40_0000, Flags::Present),
+        Err(Error::Overlap(0x4141_4000))
+    ));
+    assert_eq!(x.translate(0xc001_0000, Prot::R).unwrap(), 0x4001_0000);
+    assert_eq!(x.translate(0x4141_4000, Prot::R).unwrap(), 0x1_8181_0000);
+}
+
+#[test]
+fn split_32() {
+    let mut x = PageTable32::default();
+    let f = Flags::Present | Flags::User | Flags::AttributeTable;
+
+    // the frame is below 4gb, so a pte holds the rest of the page
+    x.insert_large(0x8000_0000, 0x40_0000, f).unwrap();
+    x.insert(0x8000_1000, 0x1000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    assert_eq!(x.translate(0x8000_1000, Prot::W).unwrap(), 0x1000);
+    assert_eq!(x.translate(0x8000_0123, Prot::R).unwrap(), 0x40_0123);
+    assert_eq!(x.translate(0x803f_f000, Prot::R).unwrap(), 0x7f_f000);
+    assert_eq!(x.translate(0x8000_2000, Prot::W), None);
+
+    let (cr3, tables) = x.commit_at(0x10_0000).unwrap();
+    let pt = (entry32(&tables[&cr3], 0x200) & !0xfff) as u64;
+
+    // the pat bit moves down from bit 12 to bit 7
+    assert_eq!(entry32(&tables[&pt], 0), 0x40_0000 | 1 << 7 | 0b101);
+    assert_eq!(entry32(&tables[&pt], 1), 0x1000 | 0b11);
+    assert_eq!(entry32(&tables[&cr3], 0x200) & 0xfff, 0b111);
+}
+
+#[test]
+fn split_pae() {
+    let mut x = PageTablePae::default();
+    let f = Flags::Present | Flags::AttributeTable | Flags::NX;
+
+    // a pae pte holds any frame a 2mb page can, so the page is split
+    x.insert_large(0xc000_0000, 0x10_4000_0000, f).unwrap();
+    x.insert(0xc001_0000, 0x1000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    assert_eq!(x.translate(0xc001_0000, Prot::W).unwrap(), 0x1000);
+    assert_eq!(x.translate(0xc000_f000, Prot::R).unwrap(), 0x10_4000_f000);
+    assert_eq!(x.translate(0xc01f_f123, Prot::R).unwrap(), 0x10_401f_f123);
+    assert_eq!(x.translate(0xc000_0000, Prot::X), None);
+
+    let (cr3, tables) = x.commit_at(0x10_0000).unwrap();
+    let pd = entry64(&tables[&cr3], 3) & !0xfff;
+    let pt = entry64(&tables[&pd], 0) & !0xfff;
+
+    // the pat bit moves down from bit 12 to bit 7, and nx stays on the leaves
+    assert_eq!(
+        entry64(&tables[&pt], 0),
+        0x10_4000_0000 | 1 << 63 | 1 << 7 | 1
+    );
+    assert_eq!(entry64(&tables[&pt], 0x10), 0x1000 | 0b11);
+    assert_eq!(entry64(&tables[&pd], 0) & (1 << 63 | 1 << 7), 0);
+}
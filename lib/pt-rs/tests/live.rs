@@ -0,0 +1,157 @@
+extern crate pt;
+
+use pt::{Error, Flags, PageTable, Prot};
+
+const BASE: u64 = 0x10_0000;
+
+#[test]
+fn initial() {
+    let mut x = PageTable::default();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    let x = x.commit_live(BASE, 16).unwrap();
+
+    assert_eq!(x.root(), BASE);
+    assert_eq!(x.region().len(), 16);
+
+    let region = x.region();
+    let y = PageTable::from_physical(x.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4141_1234, Prot::W), Some(0x8181_1234));
+}
+
+#[test]
+fn patch_in_place() {
+    let mut x = PageTable::default();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    let mut x = x.commit_live(BASE, 16).unwrap();
+
+    // the root, pdpt, pd and pt were laid out in that order
+    let pt = BASE + 0x3000;
+
+    assert_eq!(
+        x.insert(0x4141_2000, 0x8181_2000, Flags::Present | Flags::Writable)
+            .unwrap(),
+        vec![pt]
+    );
+    assert_eq!(
+        x.protect(0x4141_0000, 0x1000, Flags::Present).unwrap(),
+        vec![pt]
+    );
+
+    // new tables, and the entry in the pd pointing at the new pt
+    let dirty = x
+        .insert(0x4160_0000, 0x8181_3000, Flags::Present | Flags::Writable)
+        .unwrap();
+    assert_eq!(dirty, vec![BASE + 0x2000, BASE + 0x4000]);
+
+    // unchanged entries don't dirty anything
+    assert_eq!(
+        x.protect(0x4141_0000, 0x1000, Flags::Present).unwrap(),
+        vec![]
+    );
+    assert_eq!(x.remove(0x5000_0000).unwrap(), vec![]);
+
+    let region = x.region();
+    let y = PageTable::from_physical(x.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4141_0000, Prot::W), None);
+    assert_eq!(y.translate(0x4141_2000, Prot::W), Some(0x8181_2000));
+    assert_eq!(y.translate(0x4160_0000, Prot::R), Some(0x8181_3000));
+}
+
+#[test]
+fn stable_gpas() {
+    let mut x = PageTable::default();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let mut x = x.commit_live(BASE, 16).unwrap();
+
+    // freeing the pt clears its page and the entry pointing at it
+    x.insert(0x4160_0000, 0x8181_3000, Flags::Present).unwrap();
+    assert_eq!(
+        x.remove(0x4160_0000).unwrap(),
+        vec![BASE + 0x2000, BASE + 0x4000]
+    );
+
+    // the freed page is reused at the same address
+    let dirty = x.insert(0x8000_0000, 0x8181_4000, Flags::Present).unwrap();
+    assert_eq!(dirty, vec![BASE + 0x1000, BASE + 0x4000, BASE + 0x5000]);
+
+    let region = x.region();
+    let y = PageTable::from_physical(x.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4160_0000, Prot::R), None);
+    assert_eq!(y.translate(0x8000_0000, Prot::R), Some(0x8181_4000));
+    assert_eq!(y.translate(0x4141_0000, Prot::R), Some(0x8181_0000));
+}
+
+#[test]
+fn remove_large() {
+    let mut x = PageTable::default();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let mut x = x.commit_live(BASE, 16).unwrap();
+
+    x.insert_large(0x4160_0000, 0x8160_0000, Flags::Present)
+        .unwrap();
+
+    // the split page goes out with the pd, and the rest stays mapped
+    let dirty = x.remove(0x4160_1000).unwrap();
+    assert_eq!(dirty, vec![BASE + 0x2000, BASE + 0x4000]);
+
+    let region = x.region();
+    let y = PageTable::from_physical(x.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4160_1000, Prot::R), None);
+    assert_eq!(y.translate(0x4160_2000, Prot::R), Some(0x8160_2000));
+    assert_eq!(y.translate(0x4141_0000, Prot::R), Some(0x8181_0000));
+}
+
+#[test]
+fn capacity() {
+    let mut x = PageTable::default();
+    x.insert(0, 0x8000_0000, Flags::Present).unwrap();
+
+    let mut x = x.commit_live(BASE, 5).unwrap();
+
+    assert!(x.insert(0x20_0000, 0x8000_1000, Flags::Present).is_ok());
+    assert!(matches!(
+        x.insert(0x4000_0000, 0x8000_2000, Flags::Present),
+        Err(Error::Capacity(_))
+    ));
+
+    // neither the region nor the table kept the change
+    let region = x.region();
+    let y = PageTable::from_physical(x.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4000_0000, Prot::R), None);
+    assert_eq!(x.table().translate(0x4000_0000, Prot::R), None);
+    assert_eq!(x.sync().unwrap(), vec![]);
+
+    // so a change which fits still goes through
+    assert!(x.insert(0x1000, 0x8000_3000, Flags::Present).is_ok());
+    let region = x.region();
+    let y = PageTable::from_physical(x.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x1000, Prot::R), Some(0x8000_3000));
+}
+
+#[test]
+fn overlap() {
+    let mut x = PageTable::default();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let mut x = x.commit_live(BASE, 16).unwrap();
+
+    assert!(matches!(
+        x.insert(0x4141_3000, BASE + 0x8000, Flags::Present),
+        Err(Error::TableOverlap(_))
+    ));
+    assert_eq!(x.table().translate(0x4141_3000, Prot::R), None);
+
+    // the rejected page doesn't hold up later changes
+    assert!(x.insert(0x4141_3000, 0x8181_3000, Flags::Present).is_ok());
+    let region = x.region();
+    let y = PageTable::from_physical(x.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4141_3000, Prot::R), Some(0x8181_3000));
+
+    assert!(PageTable::default().commit_live(0x10_0800, 1).is_err());
+}
@@ -0,0 +1,76 @@
+extern crate pt;
+
+use pt::{Error, Flags, PageTable, Prot};
+
+#[test]
+fn map_range_small() {
+    let mut x = PageTable::default();
+
+    x.map_range(0x4141_0000, 0x8181_0000, 0x3000, Flags::Present)
+        .unwrap();
+
+    assert_eq!(x.translate(0x4141_0000, Prot::R).unwrap(), 0x8181_0000);
+    assert_eq!(x.translate(0x4141_2fff, Prot::R).unwrap(), 0x8181_2fff);
+    assert_eq!(x.translate(0x4141_3000, Prot::R), None);
+}
+
+#[test]
+fn map_range_large() {
+    let mut x = PageTable::default();
+
+    // 4kb up to the first 2mb boundary, 2mb pages up to the 1gb boundary, a
+    // 1gb page, then 2mb and 4kb pages for the tail
+    let (vaddr, paddr) = (0x3fe0_0000 - 0x1000, 0x7fe0_0000 - 0x1000);
+    let len = 0x1000 + 0x20_0000 + 0x4000_0000 + 0x20_0000 + 0x1000;
+
+    x.map_range(vaddr, paddr, len, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    for off in (0..len).step_by(0x1000) {
+        assert_eq!(x.translate(vaddr + off, Prot::W).unwrap(), paddr + off);
+    }
+    assert_eq!(x.translate(vaddr + len, Prot::R), None);
+
+    assert!(x.pte(0x3fdf_f000).is_some());
+    assert!(x.pte(0x3fe0_0000).is_none());
+    assert!(x.pt(0x4000_0000).is_none());
+    assert!(x.pte(0x8000_0000).is_none());
+    assert!(x.pte(0x8020_0000).is_some());
+}
+
+#[test]
+fn map_range_misaligned() {
+    let mut x = PageTable::default();
+
+    assert!(matches!(
+        x.map_range(0x4141_0800, 0x8181_0000, 0x1000, Flags::Present),
+        Err(Error::Misaligned(0x4141_0800))
+    ));
+    assert!(matches!(
+        x.map_range(0x4141_0000, 0x8181_0000, 0x1001, Flags::Present),
+        Err(Error::Misaligned(0x1001))
+    ));
+}
+
+#[test]
+fn map_range_overlap() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_4000, 0x1234_0000, Flags::Present).unwrap();
+    x.insert_large(0x8000_0000, 0x1240_0000, Flags::Present)
+        .unwrap();
+
+    assert!(matches!(
+        x.map_range(0x4140_0000, 0x8180_0000, 0x20_0000, Flags::Present),
+        Err(Error::Overlap(0x4141_4000))
+    ));
+    assert!(matches!(
+        x.map_range(0x801f_f000, 0x8180_0000, 0x2000, Flags::Present),
+        Err(Error::Overlap(0x801f_f000))
+    ));
+
+    // nothing was mapped by the failed calls
+    assert_eq!(x.translate(0x4140_0000, Prot::R), None);
+    assert_eq!(x.translate(0x4141_4000, Prot::R).unwrap(), 0x1234_0000);
+    assert_eq!(x.translate(0x8020_0000, Prot::R), None);
+}
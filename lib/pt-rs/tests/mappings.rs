@@ -0,0 +1,133 @@
+extern crate pt;
+
+use pt::{Flags, Mapping, PageTable, Paging};
+
+#[test]
+fn mappings() {
+    let mut x = PageTable::default();
+
+    x.insert(0xffff_8000_0000_1000, 0x1000, Flags::Present)
+        .unwrap();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert_large(0x4160_0000, 0x8160_0000, Flags::Present | Flags::NX)
+        .unwrap();
+    x.insert_huge(0x80_0000_0000, 0x4000_0000, Flags::Present | Flags::User)
+        .unwrap();
+
+    let y: Vec<Mapping> = x.mappings().collect();
+
+    assert_eq!(
+        y,
+        vec![
+            Mapping {
+                vaddr: 0x4141_0000,
+                paddr: 0x8181_0000,
+                size: 0x1000,
+                flags: Flags::Present | Flags::Writable,
+            },
+            Mapping {
+                vaddr: 0x4160_0000,
+                paddr: 0x8160_0000,
+                size: 0x20_0000,
+                flags: Flags::Present | Flags::NX,
+            },
+            Mapping {
+                vaddr: 0x80_0000_0000,
+                paddr: 0x4000_0000,
+                size: 0x4000_0000,
+                flags: Flags::Present | Flags::User,
+            },
+            Mapping {
+                vaddr: 0xffff_8000_0000_1000,
+                paddr: 0x1000,
+                size: 0x1000,
+                flags: Flags::Present,
+            },
+        ]
+    );
+}
+
+#[test]
+fn mappings_effective_flags() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.protect(0x4141_0000, 0x1000, Flags::Present | Flags::User)
+        .unwrap();
+
+    let y: Vec<Mapping> = x.mappings().collect();
+
+    assert_eq!(y.len(), 1);
+    assert_eq!(y[0].flags, Flags::Present | Flags::User);
+}
+
+#[test]
+fn mappings_not_present() {
+    let mut x = PageTable::default();
+
+    x.insert(0x1000, 0x2000, Flags::Writable).unwrap();
+    x.insert_large(0x20_0000, 0x20_0000, Flags::User).unwrap();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.protect(0x4141_0000, 0x1000, Flags::empty()).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present).unwrap();
+
+    let y: Vec<u64> = x.mappings().map(|m| m.vaddr).collect();
+
+    assert_eq!(y, vec![0x4141_1000]);
+}
+
+#[test]
+fn mappings_la57() {
+    let mut x = PageTable::new(Paging::Level5);
+
+    x.insert(0xff41_4141_4141_4000, 0x1234_0000, Flags::Present)
+        .unwrap();
+    x.insert(0x1_4141_4141_4000, 0x8181_0000, Flags::Present)
+        .unwrap();
+
+    let y: Vec<u64> = x.mappings().map(|m| m.vaddr).collect();
+
+    assert_eq!(y, vec![0x1_4141_4141_4000, 0xff41_4141_4141_4000]);
+}
+
+#[test]
+fn mappings_coalesce() {
+    let mut x = PageTable::default();
+
+    x.map_range(0x4000_0000, 0x8000_0000, 0x4020_3000, Flags::Present)
+        .unwrap();
+    x.insert(0x8020_3000, 0xc020_3000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x8020_4000, 0x1234_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    assert_eq!(x.mappings().count(), 1 + 1 + 3 + 1 + 1);
+
+    let y: Vec<Mapping> = x.mappings().coalesce().collect();
+
+    assert_eq!(
+        y,
+        vec![
+            Mapping {
+                vaddr: 0x4000_0000,
+                paddr: 0x8000_0000,
+                size: 0x4020_3000,
+                flags: Flags::Present,
+            },
+            Mapping {
+                vaddr: 0x8020_3000,
+                paddr: 0xc020_3000,
+                size: 0x1000,
+                flags: Flags::Present | Flags::Writable,
+            },
+            Mapping {
+                vaddr: 0x8020_4000,
+                paddr: 0x1234_0000,
+                size: 0x1000,
+                flags: Flags::Present | Flags::Writable,
+            },
+        ]
+    );
+}
@@ -0,0 +1,157 @@
+extern crate pt;
+
+use std::collections::BTreeMap;
+
+use memmap::MmapMut;
+
+use pt::{Flags, MemoryType, PageTable, Paging, PAT_DEFAULT};
+
+// WB, WC, UC-, UC, WB, WP, UC-, WT
+const PAT: u64 = 0x0407_0506_0007_0106;
+
+const PAT_4K: u64 = 1 << 7;
+const PAT_LARGE: u64 = 1 << 12;
+
+// the raw entry `depth` tables below cr3 on the walk to vaddr
+fn entry(tables: &BTreeMap<u64, MmapMut>, cr3: u64, vaddr: u64, depth: usize) -> u64 {
+    let mut table = cr3;
+    let mut e = 0;
+
+    for shift in [39, 30, 21, 12].iter().take(depth + 1) {
+        let off = ((vaddr >> shift) as usize & 0x1ff) * 8;
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&tables[&table][off..off + 8]);
+
+        e = u64::from_le_bytes(b);
+        table = e & 0x000f_ffff_ffff_f000;
+    }
+
+    e
+}
+
+#[test]
+fn encode() {
+    let f = Flags::Present;
+
+    assert_eq!(
+        f.with_memory_type(MemoryType::WriteBack, PAT_DEFAULT),
+        Some(f)
+    );
+    assert_eq!(
+        f.with_memory_type(MemoryType::Uncacheable, PAT_DEFAULT),
+        Some(f | Flags::WriteThrough | Flags::CacheDisabled)
+    );
+    assert_eq!(
+        f.with_memory_type(MemoryType::WriteCombining, PAT_DEFAULT),
+        None
+    );
+    assert_eq!(
+        f.with_memory_type(MemoryType::WriteProtected, PAT),
+        Some(f | Flags::AttributeTable | Flags::WriteThrough)
+    );
+
+    // the previous type is replaced
+    let uc = f
+        .with_memory_type(MemoryType::Uncacheable, PAT)
+        .and_then(|f| f.with_memory_type(MemoryType::WriteCombining, PAT));
+    assert_eq!(uc, Some(f | Flags::WriteThrough));
+
+    for ty in [
+        MemoryType::Uncacheable,
+        MemoryType::WriteCombining,
+        MemoryType::WriteThrough,
+        MemoryType::WriteProtected,
+        MemoryType::WriteBack,
+        MemoryType::UncacheableMinus,
+    ]
+    .iter()
+    {
+        let f = f.with_memory_type(*ty, PAT).unwrap();
+        assert_eq!(f.memory_type(PAT), Some(*ty));
+    }
+}
+
+#[test]
+fn page_sizes() {
+    let mut x = PageTable::default();
+    let wp = (Flags::Present | Flags::Writable)
+        .with_memory_type(MemoryType::WriteProtected, PAT)
+        .unwrap();
+    let wt = Flags::Present
+        .with_memory_type(MemoryType::WriteThrough, PAT)
+        .unwrap();
+
+    x.insert(0x4141_0000, 0x8181_0000, wp).unwrap();
+    x.insert_large(0x4160_0000, 0x8160_0000, wp).unwrap();
+    x.insert_huge(0x8000_0000, 0x4000_0000, wt).unwrap();
+
+    let types: Vec<_> = x.mappings().map(|m| m.memory_type(PAT)).collect();
+    assert_eq!(
+        types,
+        vec![
+            Some(MemoryType::WriteProtected),
+            Some(MemoryType::WriteProtected),
+            Some(MemoryType::WriteThrough),
+        ]
+    );
+
+    let (cr3, tables) = x.commit().unwrap();
+
+    // the PAT bit is bit 7 of a pte, and bit 12 of large pages, where bit 7 is
+    // the page size
+    let pte = entry(&tables, cr3, 0x4141_0000, 3);
+    assert_eq!(pte & (PAT_4K | PAT_LARGE), PAT_4K);
+    assert_eq!(pte & 0x000f_ffff_ffff_f000, 0x8181_0000);
+
+    let pde = entry(&tables, cr3, 0x4160_0000, 2);
+    assert_eq!(pde & (PAT_4K | PAT_LARGE), PAT_4K | PAT_LARGE);
+    assert_eq!(pde & 0x000f_ffff_ffe0_0000, 0x8160_0000);
+
+    let pdpte = entry(&tables, cr3, 0x8000_0000, 1);
+    assert_eq!(pdpte & (PAT_4K | PAT_LARGE), PAT_4K | PAT_LARGE);
+
+    // the table above the 4kb page doesn't pick up the PAT bit as a page size
+    let table = entry(&tables, cr3, 0x4141_0000, 2);
+    assert_eq!(table & PAT_4K, 0);
+
+    // nor PWT and PCD, which would set the memory type of the pt itself
+    for depth in 0..3 {
+        assert_eq!(entry(&tables, cr3, 0x4141_0000, depth) & 0x18, 0);
+    }
+
+    let y = PageTable::from_committed(Paging::Level4, cr3, &tables).unwrap();
+    let types: Vec<_> = y.mappings().map(|m| m.memory_type(PAT)).collect();
+    assert_eq!(
+        types,
+        vec![
+            Some(MemoryType::WriteProtected),
+            Some(MemoryType::WriteProtected),
+            Some(MemoryType::WriteThrough),
+        ]
+    );
+}
+
+#[test]
+fn split() {
+    let mut x = PageTable::default();
+    let wc = (Flags::Present | Flags::Writable)
+        .with_memory_type(MemoryType::WriteCombining, PAT)
+        .unwrap();
+    let wp = Flags::Present
+        .with_memory_type(MemoryType::WriteProtected, PAT)
+        .unwrap();
+
+    x.insert_huge(0x4000_0000, 0x4000_0000, wp).unwrap();
+    x.protect(0x4000_0000, 0x1000, wc).unwrap();
+
+    let mut types = x.mappings().map(|m| (m.size, m.memory_type(PAT)));
+    assert_eq!(
+        types.next(),
+        Some((0x1000, Some(MemoryType::WriteCombining)))
+    );
+    assert_eq!(
+        types.next(),
+        Some((0x1000, Some(MemoryType::WriteProtected)))
+    );
+    assert!(types.all(|(_, ty)| ty == Some(MemoryType::WriteProtected)));
+}
@@ -0,0 +1,269 @@
+extern crate pt;
+
+use std::io::{Error, ErrorKind};
+
+use pt::{Context, EptFlags, EptTable, Flags, Level, NestedFault, PageTable, Paging, Prot};
+
+// where the guest's tables are committed, and the offset of host memory from
+// guest memory
+const TABLES: u64 = 0x10_0000;
+const HOST: u64 = 0x1_0000_0000;
+
+#[test]
+fn translate() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(
+        0x4141_1000,
+        0x8181_1000,
+        Flags::Present | Flags::User | Flags::Writable,
+    )
+    .unwrap();
+
+    // preorder: pml4, pdpt, pd, pt
+    let guest = x.commit_contiguous(TABLES).unwrap();
+    let cr3 = guest.root();
+    let read = |hpa: u64, buf: &mut [u8]| guest.read(hpa.wrapping_sub(HOST), buf);
+
+    let mut x = EptTable::default();
+    let ctx = Context::default();
+
+    x.map_range(TABLES, TABLES + HOST, 0x4000, EptFlags::Read)
+        .unwrap();
+    x.map_range(
+        0x8181_0000,
+        0x8181_0000 + HOST,
+        0x2000,
+        EptFlags::Read | EptFlags::Write,
+    )
+    .unwrap();
+
+    assert_eq!(
+        x.translate_nested(Paging::Level4, cr3, 0x4141_0123, Prot::W, &ctx, read)
+            .unwrap(),
+        Ok(0x8181_0123 + HOST)
+    );
+    assert_eq!(
+        x.translate_nested(Paging::Level4, cr3, 0x4141_1123, Prot::R, &ctx, read)
+            .unwrap(),
+        Ok(0x8181_1123 + HOST)
+    );
+}
+
+#[test]
+fn guest_fault() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(
+        0x4141_1000,
+        0x8181_1000,
+        Flags::Present | Flags::User | Flags::Writable,
+    )
+    .unwrap();
+
+    let guest = x.commit_contiguous(TABLES).unwrap();
+    let cr3 = guest.root();
+    let read = |hpa: u64, buf: &mut [u8]| guest.read(hpa.wrapping_sub(HOST), buf);
+
+    // only the guest's tables are mapped, the faults come before the final
+    // access
+    let mut x = EptTable::default();
+    x.map_range(TABLES, TABLES + HOST, 0x4000, EptFlags::Read)
+        .unwrap();
+
+    let ctx = Context::default();
+    match x
+        .translate_nested(Paging::Level4, cr3, 0x4141_2000, Prot::R, &ctx, read)
+        .unwrap()
+    {
+        Err(NestedFault::Guest(pf)) => assert_eq!(pf.level, Level::Pt),
+        r => panic!("{:?}", r),
+    }
+
+    // guest permissions are checked before the final access
+    let ctx = Context::user();
+    match x
+        .translate_nested(Paging::Level4, cr3, 0x4141_0000, Prot::R, &ctx, read)
+        .unwrap()
+    {
+        Err(NestedFault::Guest(pf)) => assert_eq!(pf.level, Level::Pt),
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn table_violation() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    // preorder: pml4, pdpt, pd, pt
+    let guest = x.commit_contiguous(TABLES).unwrap();
+    let cr3 = guest.root();
+    let read = |hpa: u64, buf: &mut [u8]| guest.read(hpa.wrapping_sub(HOST), buf);
+
+    // the guest's page directory isn't mapped by the EPT
+    let mut x = EptTable::default();
+    x.map_range(TABLES, TABLES + HOST, 0x2000, EptFlags::Read)
+        .unwrap();
+    x.insert(TABLES + 0x3000, TABLES + 0x3000 + HOST, EptFlags::Read)
+        .unwrap();
+    x.insert(0x8181_0000, 0x8181_0000 + HOST, EptFlags::Read)
+        .unwrap();
+
+    let ctx = Context::default();
+    match x
+        .translate_nested(Paging::Level4, cr3, 0x4141_0000, Prot::R, &ctx, read)
+        .unwrap()
+    {
+        Err(NestedFault::Ept(v, Some(Level::Pd))) => {
+            assert_eq!(v.gpa, TABLES + 0x2000 + 0xa * 8);
+            assert_eq!(v.access, Prot::R);
+            assert!(!v.present);
+        }
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn final_violation() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(
+        0x4141_1000,
+        0x8181_1000,
+        Flags::Present | Flags::User | Flags::Writable,
+    )
+    .unwrap();
+
+    let guest = x.commit_contiguous(TABLES).unwrap();
+    let cr3 = guest.root();
+    let read = |hpa: u64, buf: &mut [u8]| guest.read(hpa.wrapping_sub(HOST), buf);
+
+    let mut x = EptTable::default();
+    let ctx = Context::default();
+
+    x.map_range(TABLES, TABLES + HOST, 0x4000, EptFlags::Read)
+        .unwrap();
+    x.insert(
+        0x8181_0000,
+        0x8181_0000 + HOST,
+        EptFlags::Read | EptFlags::Write | EptFlags::Execute,
+    )
+    .unwrap();
+    x.insert(
+        0x8181_1000,
+        0x8181_1000 + HOST,
+        EptFlags::Read | EptFlags::UserExecute,
+    )
+    .unwrap();
+
+    match x
+        .translate_nested(Paging::Level4, cr3, 0x4141_1000, Prot::W, &ctx, read)
+        .unwrap()
+    {
+        Err(NestedFault::Ept(v, None)) => {
+            assert_eq!(v.gpa, 0x8181_1000);
+            assert_eq!(v.level, Level::Pt);
+            assert!(v.present);
+            assert!(!v.misconfigured);
+        }
+        r => panic!("{:?}", r),
+    }
+
+    // with mode-based execute control the user page is only executable
+    // from user mode, and the supervisor page only from supervisor mode
+    x.set_mode_based_execute(true);
+
+    let user = Context::user();
+    assert_eq!(
+        x.translate_nested(Paging::Level4, cr3, 0x4141_1000, Prot::X, &user, read)
+            .unwrap(),
+        Ok(0x8181_1000 + HOST)
+    );
+    assert!(x
+        .translate_nested(Paging::Level4, cr3, 0x4141_0000, Prot::X, &ctx, read)
+        .unwrap()
+        .is_ok());
+
+    x.insert(
+        0x8181_0000,
+        0x8181_0000 + HOST,
+        EptFlags::Read | EptFlags::UserExecute,
+    )
+    .unwrap();
+    assert!(x
+        .translate_nested(Paging::Level4, cr3, 0x4141_0000, Prot::X, &ctx, read)
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn user_fetch() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::User)
+        .unwrap();
+
+    let guest = x.commit_contiguous(TABLES).unwrap();
+    let cr3 = guest.root();
+    let read = |hpa: u64, buf: &mut [u8]| guest.read(hpa.wrapping_sub(HOST), buf);
+
+    let mut x = EptTable::default();
+    let ctx = Context::user();
+
+    x.map_range(TABLES, TABLES + HOST, 0x4000, EptFlags::Read)
+        .unwrap();
+    x.insert(
+        0x8181_1000,
+        0x8181_1000 + HOST,
+        EptFlags::Read | EptFlags::Execute,
+    )
+    .unwrap();
+
+    // without mode-based execute control, execute covers user fetches too
+    assert_eq!(
+        x.translate_nested(Paging::Level4, cr3, 0x4141_1000, Prot::X, &ctx, read)
+            .unwrap(),
+        Ok(0x8181_1000 + HOST)
+    );
+
+    x.set_mode_based_execute(true);
+    match x
+        .translate_nested(Paging::Level4, cr3, 0x4141_1000, Prot::X, &ctx, read)
+        .unwrap()
+    {
+        Err(NestedFault::Ept(v, None)) => assert!(v.present),
+        r => panic!("{:?}", r),
+    }
+}
+
+#[test]
+fn read_error() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    let guest = x.commit_contiguous(TABLES).unwrap();
+
+    let mut x = EptTable::default();
+    x.map_range(TABLES, TABLES + HOST, 0x4000, EptFlags::Read)
+        .unwrap();
+
+    assert!(x
+        .translate_nested(
+            Paging::Level4,
+            guest.root(),
+            0x4141_0000,
+            Prot::R,
+            &Context::default(),
+            |_, _| Err(Error::from(ErrorKind::NotFound))
+        )
+        .is_err());
+}
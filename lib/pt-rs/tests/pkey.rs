@@ -0,0 +1,139 @@
+extern crate pt;
+
+use pt::{Context, Flags, Level, PageFault, PageTable, PfCode, Prot};
+
+#[test]
+fn keys() {
+    let mut x = PageTable::default();
+
+    assert_eq!(Flags::Present.with_key(0xa).with_key(2).key(), 2);
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present.with_key(3))
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present.with_key(5))
+        .unwrap();
+    x.map_range(
+        0x4160_0000,
+        0x8160_0000,
+        0x20_0000,
+        Flags::Present.with_key(1),
+    )
+    .unwrap();
+
+    let keys: Vec<u8> = x.mappings().map(|m| m.flags.key()).collect();
+    assert_eq!(keys, vec![3, 5, 1]);
+
+    // remapping a page replaces its key
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present.with_key(4))
+        .unwrap();
+    assert_eq!(x.mappings().next().unwrap().flags.key(), 4);
+}
+
+#[test]
+fn pkru() {
+    let mut x = PageTable::default();
+    let mut ctx = Context::user();
+
+    x.insert(
+        0x4141_0000,
+        0x8181_0000,
+        (Flags::Present | Flags::Writable | Flags::User).with_key(3),
+    )
+    .unwrap();
+    x.insert_large(
+        0x4160_0000,
+        0x8160_0000,
+        (Flags::Present | Flags::Writable | Flags::User).with_key(1),
+    )
+    .unwrap();
+
+    // without CR4.PKE keys are ignored
+    assert_eq!(
+        x.translate_with(0x4141_0000, Prot::W, &ctx),
+        Some(0x8181_0000)
+    );
+
+    // write disable for key 3
+    ctx.pkru = Some(2 << 6);
+    assert_eq!(
+        x.translate_with(0x4141_0000, Prot::R, &ctx),
+        Some(0x8181_0000)
+    );
+    assert_eq!(
+        x.translate_detailed_with(0x4141_0000, Prot::W, &ctx),
+        Err(PageFault {
+            code: PfCode::Present | PfCode::Write | PfCode::User | PfCode::ProtectionKey,
+            level: Level::Pt,
+        })
+    );
+    assert_eq!(
+        x.translate_with(0x4160_0000, Prot::W, &ctx),
+        Some(0x8160_0000)
+    );
+
+    // access disable for key 1, fetches aren't affected
+    ctx.pkru = Some(1 << 2);
+    assert_eq!(
+        x.translate_detailed_with(0x4160_1234, Prot::R, &ctx),
+        Err(PageFault {
+            code: PfCode::Present | PfCode::User | PfCode::ProtectionKey,
+            level: Level::Pd,
+        })
+    );
+    assert_eq!(
+        x.translate_with(0x4160_0000, Prot::X, &ctx),
+        Some(0x8160_0000)
+    );
+
+    // supervisor accesses to user pages also use PKRU, and only honor write
+    // disable with CR0.WP
+    let mut ctx = Context::supervisor();
+    ctx.pkru = Some(2 << 6);
+    assert_eq!(x.translate_with(0x4141_0000, Prot::W, &ctx), None);
+
+    ctx.wp = false;
+    assert_eq!(
+        x.translate_with(0x4141_0000, Prot::W, &ctx),
+        Some(0x8181_0000)
+    );
+}
+
+#[test]
+fn pkrs() {
+    let mut x = PageTable::default();
+    let mut ctx = Context::supervisor();
+
+    x.insert(
+        0x4141_0000,
+        0x8181_0000,
+        (Flags::Present | Flags::User).with_key(3),
+    )
+    .unwrap();
+    x.insert(
+        0x4141_1000,
+        0x8181_1000,
+        (Flags::Present | Flags::Writable).with_key(5),
+    )
+    .unwrap();
+
+    ctx.pkrs = Some(1 << 10);
+    assert_eq!(
+        x.translate_detailed_with(0x4141_1000, Prot::R, &ctx),
+        Err(PageFault {
+            code: PfCode::Present | PfCode::ProtectionKey,
+            level: Level::Pt,
+        })
+    );
+    assert_eq!(
+        x.translate_with(0x4141_0000, Prot::R, &ctx),
+        Some(0x8181_0000)
+    );
+
+    // PKRU doesn't apply to supervisor pages
+    ctx.pkrs = None;
+    ctx.pkru = Some(!0);
+    assert_eq!(
+        x.translate_with(0x4141_1000, Prot::W, &ctx),
+        Some(0x8181_1000)
+    );
+}
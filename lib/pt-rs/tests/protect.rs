@@ -0,0 +1,197 @@
+extern crate pt;
+
+use std::collections::BTreeMap;
+
+use memmap::MmapMut;
+
+use pt::{Flags, PageTable, Paging, Prot};
+
+// follow the walk for vaddr through committed tables, returning every entry
+fn walk(cr3: u64, tables: &BTreeMap<u64, MmapMut>, vaddr: u64) -> Vec<u64> {
+    let mut r = vec![];
+    let mut table = cr3;
+
+    for shift in [39, 30, 21, 12].iter() {
+        let idx = (vaddr >> shift) as usize & 0x1ff;
+        let mut b = [0; 8];
+        b.copy_from_slice(&tables[&table][idx * 8..idx * 8 + 8]);
+
+        let entry = u64::from_le_bytes(b);
+        r.push(entry);
+
+        match tables.get(&(entry & 0x000f_ffff_ffff_f000)) {
+            Some(_) if entry & 0x80 == 0 => table = entry & 0x000f_ffff_ffff_f000,
+            _ => break,
+        }
+    }
+
+    r
+}
+
+#[test]
+fn remove() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present).unwrap();
+
+    assert!(x.remove(0x4141_0123).unwrap());
+    assert!(!x.remove(0x4141_0000).unwrap());
+    assert_eq!(x.translate(0x4141_0000, Prot::R), None);
+    assert_eq!(x.translate(0x4141_1000, Prot::R).unwrap(), 0x8181_1000);
+
+    // removing the last page frees every table above it
+    assert!(x.remove(0x4141_1000).unwrap());
+    assert!(x.pt(0x4141_1000).is_none());
+    assert!(x.pd(0x4141_1000).is_none());
+    assert!(x.pdpt(0x4141_1000).is_none());
+
+    let (_, y) = x.commit().unwrap();
+    assert_eq!(y.len(), 1);
+}
+
+#[test]
+fn remove_large() {
+    let mut x = PageTable::new(Paging::Level5);
+
+    x.insert_large(0x4140_0000, 0x8180_0000, Flags::Present)
+        .unwrap();
+    x.insert_huge(0x1_0000_0000, 0x4000_0000, Flags::Present)
+        .unwrap();
+
+    // only the 4kb page is unmapped, the rest of the large page is split
+    assert!(x.remove(0x4141_4141).unwrap());
+    assert!(x.remove(0x1_2345_6789).unwrap());
+    assert!(!x.remove(0x4141_4000).unwrap());
+
+    assert_eq!(x.translate(0x4141_4000, Prot::R), None);
+    assert_eq!(x.translate(0x4141_3fff, Prot::R).unwrap(), 0x8181_3fff);
+    assert_eq!(x.translate(0x4141_5000, Prot::R).unwrap(), 0x8181_5000);
+    assert_eq!(x.translate(0x1_2345_6000, Prot::R), None);
+    assert_eq!(x.translate(0x1_2345_7000, Prot::R).unwrap(), 0x6345_7000);
+    assert_eq!(x.translate(0x1_0000_0000, Prot::R).unwrap(), 0x4000_0000);
+
+    // only down to the size of the page removed
+    assert!(x.pte(0x4141_5000).is_some());
+    assert!(x.pte(0x1_2345_7000).is_some());
+    assert!(x.pt(0x1_2360_0000).unwrap().is_leaf());
+}
+
+#[test]
+fn insert_nx_is_not_sticky() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::NX)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present).unwrap();
+
+    assert_eq!(x.translate(0x4141_0000, Prot::X), None);
+    assert_eq!(x.translate(0x4141_1000, Prot::X).unwrap(), 0x8181_1000);
+}
+
+#[test]
+fn reinsert_replaces_flags() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    assert_eq!(x.translate(0x4141_0000, Prot::W), None);
+    assert_eq!(x.pt(0x4141_0000).unwrap().flags(), Flags::Present);
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::NX)
+        .unwrap();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    assert_eq!(x.translate(0x4141_0000, Prot::X).unwrap(), 0x8181_0000);
+    assert!(!x.pte(0x4141_0000).unwrap().flags().contains(Flags::NX));
+}
+
+#[test]
+fn remove_relaxes_parents() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::NX)
+        .unwrap();
+    assert!(x.remove(0x4141_0000).unwrap());
+
+    let (cr3, y) = x.commit().unwrap();
+
+    for entry in walk(cr3, &y, 0x4141_1000).iter() {
+        assert_eq!(*entry & 0x2, 0);
+        assert_eq!(*entry >> 63, 1);
+    }
+}
+
+#[test]
+fn protect() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    x.protect(0x4141_0000, 0x1000, Flags::Present | Flags::NX)
+        .unwrap();
+
+    assert_eq!(x.translate(0x4141_0000, Prot::W), None);
+    assert_eq!(x.translate(0x4141_0000, Prot::X), None);
+    assert_eq!(x.translate(0x4141_0000, Prot::R).unwrap(), 0x8181_0000);
+    assert_eq!(x.translate(0x4141_1000, Prot::W).unwrap(), 0x8181_1000);
+
+    x.protect(0x4141_0000, 0x2000, Flags::Present).unwrap();
+
+    let (cr3, y) = x.commit().unwrap();
+
+    // the tables are no longer writable once nothing below them is
+    for entry in walk(cr3, &y, 0x4141_1000).iter() {
+        assert_eq!(*entry & 0x2, 0);
+        assert_eq!(*entry >> 63, 0);
+    }
+}
+
+#[test]
+fn protect_frame_zero() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    // without any flags the entry is empty, so the page is gone along with
+    // its tables
+    x.protect(0x4141_0000, 0x1000, Flags::empty()).unwrap();
+    assert_eq!(x.translate(0x4141_0000, Prot::R), None);
+    assert!(x.pt(0x4141_0000).is_none());
+    assert!(x.pdpt(0x4141_0000).is_none());
+
+    let (_, y) = x.commit().unwrap();
+    assert_eq!(y.len(), 1);
+}
+
+#[test]
+fn protect_splits_large() {
+    let mut x = PageTable::default();
+
+    x.insert_large(0x4140_0000, 0x8180_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert_huge(0x8000_0000, 0x4000_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    // covers all of the 2mb page, so it stays large
+    x.protect(0x4140_0000, 0x20_0000, Flags::Present).unwrap();
+    assert!(x.pte(0x4140_0000).is_none());
+    assert_eq!(x.translate(0x4141_4141, Prot::W), None);
+
+    // only the middle of the 1gb page
+    x.protect(0x8020_1000, 0x1000, Flags::Present).unwrap();
+
+    assert_eq!(x.translate(0x8020_1000, Prot::W), None);
+    assert_eq!(x.translate(0x8020_1234, Prot::R).unwrap(), 0x4020_1234);
+    assert_eq!(x.translate(0x8020_2000, Prot::W).unwrap(), 0x4020_2000);
+    assert_eq!(x.translate(0x8040_0000, Prot::W).unwrap(), 0x4040_0000);
+    assert_eq!(x.translate(0xbfff_ffff, Prot::W).unwrap(), 0x7fff_ffff);
+    assert!(x.pte(0x8020_1000).is_some());
+    assert!(x.pte(0x8040_0000).is_none());
+}
@@ -0,0 +1,105 @@
+extern crate pt;
+
+use pt::{EptFlags, EptTable, Flags, LiveTable, PageTable, Prot, TableRegion};
+
+#[test]
+fn contiguous() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert_large(0x4000_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+
+    let mut region = x.commit_contiguous(0x10_0000).unwrap();
+
+    assert_eq!(region.root(), 0x10_0000);
+    assert_eq!(region.gpa(), 0x10_0000);
+
+    let pages: Vec<_> = region.pages().collect();
+    assert_eq!(pages.len(), region.len());
+
+    for (ii, (gpa, hva)) in pages.iter().enumerate() {
+        assert_eq!(*gpa, 0x10_0000 + ii as u64 * 0x1000);
+        assert_eq!(*hva as usize, pages[0].1 as usize + ii * 0x1000);
+    }
+
+    // the same tables as committing page by page
+    let (_, tables) = x.commit_at(0x10_0000).unwrap();
+    assert_eq!(tables.len(), region.len());
+
+    for (gpa, page) in tables.iter() {
+        let off = (gpa - 0x10_0000) as usize;
+        assert_eq!(&region.as_slice()[off..off + 0x1000], &page[..]);
+    }
+}
+
+#[test]
+fn read_back() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x7fff_f000, 0x8181_1000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert_large(0x4000_0000, 0x20_0000, Flags::Present)
+        .unwrap();
+
+    let region = x.commit_contiguous(0x10_0000).unwrap();
+
+    let y = PageTable::from_physical(region.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4141_0123, Prot::R), Some(0x8181_0123));
+    assert_eq!(y.translate(0x7fff_f000, Prot::W), Some(0x8181_1000));
+    assert_eq!(y.translate(0x4010_0000, Prot::R), Some(0x30_0000));
+
+    let mut buf = [0; 8];
+    assert!(region.read(0xf_f000, &mut buf).is_err());
+    assert!(region
+        .read(0x10_0000 + region.len() as u64 * 0x1000 - 4, &mut buf)
+        .is_err());
+}
+
+#[test]
+fn overlap() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    assert!(x.commit_contiguous(0x8181_0000).is_err());
+    assert!(x.commit_contiguous(0x10_0800).is_err());
+}
+
+#[test]
+fn ept() {
+    let mut x = EptTable::default();
+
+    x.insert(0x1000, 0x8181_0000, EptFlags::Read | EptFlags::Write)
+        .unwrap();
+
+    let region = x.commit_contiguous(0x10_0000).unwrap();
+    assert_eq!(region.root() & !0xfff, 0x10_0000);
+    assert_eq!(region.len(), 4);
+}
+
+#[test]
+fn huge() {
+    let mut x = PageTable::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    // only runs where the host has huge pages reserved
+    let region = match x.commit_huge(0x10_0000) {
+        Ok(region) => region,
+        Err(_) => return,
+    };
+
+    let y = PageTable::from_physical(region.root(), |gpa, buf| region.read(gpa, buf)).unwrap();
+    assert_eq!(y.translate(0x4141_0123, Prot::R), Some(0x8181_0123));
+}
+
+#[test]
+fn send() {
+    fn check<T: Send + Sync>() {}
+
+    // regions can be handed to another thread, huge pages or not
+    check::<TableRegion>();
+    check::<LiveTable>();
+}
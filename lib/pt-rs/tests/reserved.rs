@@ -0,0 +1,245 @@
+extern crate pt;
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+use memmap::MmapMut;
+
+use pt::{Flags, Level, PageFault, PageTable, Paging, PfCode, Prot};
+
+fn entry(tables: &BTreeMap<u64, MmapMut>, paddr: u64) -> u64 {
+    let page = &tables[&(paddr & !0xfff)];
+    let off = (paddr & 0xfff) as usize;
+    let mut b = [0; 8];
+
+    b.copy_from_slice(&page[off..off + 8]);
+    u64::from_le_bytes(b)
+}
+
+const P: u64 = 1;
+const W: u64 = 1 << 1;
+const PS: u64 = 1 << 7;
+
+// tables laid out by hand, with reserved bits for a 39-bit physical address
+// width in a pml4 entry, a table entry and both kinds of leaf
+fn memory() -> BTreeMap<u64, [u64; 512]> {
+    let mut pml4 = [0; 512];
+    let mut pdpt = [0; 512];
+    let mut pd = [0; 512];
+    let mut pt = [0; 512];
+
+    pml4[0] = 0x2000 | P | W;
+    pml4[1] = 0x5000 | PS | P;
+
+    pdpt[0] = 0x3000 | P | W;
+    pdpt[1] = 0x2000_0000_0000 | P;
+
+    pd[0] = 0x4000 | P | W;
+    pd[1] = 0x20_0000 | 1 << 13 | PS | P;
+    pd[2] = 0x40_0000 | PS | P;
+
+    pt[0] = 0x8181_0000 | P;
+    pt[1] = 0x80_0000_0000 | P;
+
+    let mut r = BTreeMap::new();
+    r.insert(0x1000, pml4);
+    r.insert(0x2000, pdpt);
+    r.insert(0x3000, pd);
+    r.insert(0x4000, pt);
+    r
+}
+
+fn read(phys_bits: u8) -> Result<PageTable, Error> {
+    let mem = memory();
+
+    PageTable::from_physical_with(Paging::Level4, phys_bits, 0x1000, |gpa, buf| {
+        let page = mem
+            .get(&gpa)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{:#x}", gpa)))?;
+
+        for (b, e) in buf.chunks_mut(8).zip(page.iter()) {
+            b.copy_from_slice(&e.to_le_bytes());
+        }
+
+        Ok(())
+    })
+}
+
+fn rsvd(level: Level, p: Prot) -> Result<u64, PageFault> {
+    let mut code = PfCode::Present | PfCode::Reserved;
+    code.set(PfCode::Write, p.contains(Prot::W));
+
+    Err(PageFault { code, level })
+}
+
+#[test]
+fn reserved() {
+    let x = read(39).unwrap();
+
+    assert_eq!(x.phys_bits(), 39);
+    assert_eq!(x.translate_detailed(0x123, Prot::R), Ok(0x8181_0123));
+    assert_eq!(x.translate_detailed(0x40_0123, Prot::R), Ok(0x40_0123));
+
+    assert_eq!(
+        x.translate_detailed(0x1000, Prot::R),
+        rsvd(Level::Pt, Prot::R)
+    );
+    assert_eq!(
+        x.translate_detailed(0x1000, Prot::W),
+        rsvd(Level::Pt, Prot::W)
+    );
+    assert_eq!(
+        x.translate_detailed(0x20_0000, Prot::R),
+        rsvd(Level::Pd, Prot::R)
+    );
+    assert_eq!(
+        x.translate_detailed(0x4000_0000, Prot::R),
+        rsvd(Level::Pdpt, Prot::R)
+    );
+    assert_eq!(
+        x.translate_detailed(0x80_0000_0000, Prot::R),
+        rsvd(Level::Pml4, Prot::R)
+    );
+}
+
+#[test]
+fn width() {
+    // bit 39 is part of the address on a wider cpu
+    let x = read(40).unwrap();
+
+    assert_eq!(x.translate(0x1000, Prot::R), Some(0x80_0000_0000));
+    assert_eq!(x.translate(0x4000_0000, Prot::R), None);
+
+    // the table with bit 45 set is out of range for both, and never read
+    assert!(read(52).is_err());
+
+    // an entry has no room for a wider address
+    assert_eq!(read(53).err().unwrap().kind(), ErrorKind::InvalidInput);
+    assert!(matches!(
+        PageTable::with_phys_bits(Paging::Level4, 53),
+        Err(pt::Error::PhysicalAddressWidth(53))
+    ));
+    assert!(matches!(
+        PageTable::with_phys_bits(Paging::Level4, 11),
+        Err(pt::Error::PhysicalAddressWidth(11))
+    ));
+}
+
+#[test]
+fn round_trip() {
+    let (cr3, tables) = read(39).unwrap().commit_at(0x10_0000).unwrap();
+
+    // reserved bits in leaves are written back as they were
+    let y = PageTable::from_physical_with(Paging::Level4, 39, cr3, |gpa, buf| {
+        buf.copy_from_slice(&tables[&gpa][..buf.len()]);
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(
+        y.translate_detailed(0x1000, Prot::R),
+        rsvd(Level::Pt, Prot::R)
+    );
+    assert_eq!(
+        y.translate_detailed(0x20_0000, Prot::R),
+        rsvd(Level::Pd, Prot::R)
+    );
+
+    // and so are table entries, address and all
+    assert_eq!(entry(&tables, cr3 + 8), 0x5000 | PS | P);
+    assert_eq!(
+        y.translate_detailed(0x4000_0000, Prot::R),
+        rsvd(Level::Pdpt, Prot::R)
+    );
+    assert_eq!(
+        y.translate_detailed(0x80_0000_0000, Prot::R),
+        rsvd(Level::Pml4, Prot::R)
+    );
+}
+
+#[test]
+fn unchanged() {
+    let mut x = read(39).unwrap();
+
+    // nothing can be mapped through an entry with reserved bits
+    assert!(matches!(
+        x.insert(0x80_0000_1000, 0x1000, Flags::Present),
+        Err(pt::Error::Reserved(0x80_0000_1000))
+    ));
+    assert!(matches!(
+        x.insert_huge(0x80_4000_0000, 0, Flags::Present),
+        Err(pt::Error::Reserved(_))
+    ));
+
+    // including the end of a range, which leaves the start unmapped
+    assert!(matches!(
+        x.map_range(0x3fff_f000, 0x10_0000, 0x2000, Flags::Present),
+        Err(pt::Error::Reserved(0x4000_0000))
+    ));
+    assert_eq!(x.translate(0x3fff_f000, Prot::R), None);
+
+    // changes next to it keep it as it was read
+    x.insert(0x2000, 0x1000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.protect(0, 0x100_0000_0000, Flags::Present).unwrap();
+    assert_eq!(x.mappings().count(), 3);
+
+    let (cr3, tables) = x.commit_at(0x10_0000).unwrap();
+    assert_eq!(entry(&tables, cr3 + 8), 0x5000 | PS | P);
+
+    // and removing a page under it leaves the whole entry alone
+    let mut x = read(39).unwrap();
+    assert!(matches!(
+        x.remove(0x80_0000_0000),
+        Err(pt::Error::Reserved(0x80_0000_0000))
+    ));
+    assert!(!x.remove(0x1000).unwrap());
+
+    let (cr3, tables) = x.commit_at(0x10_0000).unwrap();
+    assert_eq!(entry(&tables, cr3 + 8), 0x5000 | PS | P);
+    assert_eq!(entry(&tables, 0x10_3000 + 8), 0x80_0000_0000 | P);
+
+    // a page the size of the entry replaces it
+    let mut x = read(39).unwrap();
+    x.insert_huge(0x4000_0000, 0x4000_0000, Flags::Present)
+        .unwrap();
+    assert_eq!(x.translate(0x4000_1234, Prot::R), Some(0x4000_1234));
+    assert_eq!(x.translate(0x123, Prot::R), Some(0x8181_0123));
+
+    let (cr3, tables) = x.commit_at(0x10_0000).unwrap();
+    let pdpt = entry(&tables, cr3) & !0xfff;
+    assert_eq!(entry(&tables, pdpt + 8), 0x4000_0000 | PS | P);
+    assert_eq!(entry(&tables, cr3 + 8), 0x5000 | PS | P);
+}
+
+#[test]
+fn mappings() {
+    let mut x = read(39).unwrap();
+
+    // pages the cpu faults on aren't mapped
+    let pages: Vec<u64> = x.mappings().map(|m| m.vaddr).collect();
+    assert_eq!(pages, vec![0, 0x40_0000]);
+
+    // and can be mapped over
+    x.map_range(0x1000, 0x5000, 0x1000, Flags::Present).unwrap();
+    assert_eq!(x.translate(0x1000, Prot::R), Some(0x5000));
+}
+
+#[test]
+fn insert() {
+    let mut x = PageTable::with_phys_bits(Paging::Level4, 39).unwrap();
+
+    assert!(x
+        .insert(0x4141_0000, 0x80_0000_0000, Flags::Present)
+        .is_err());
+    assert!(x
+        .insert_large(0x4140_0000, 0x7f_ffe0_0000, Flags::Present)
+        .is_ok());
+    assert!(x
+        .map_range(0, 0x7f_ffff_f000, 0x2000, Flags::Present)
+        .is_err());
+
+    // the tables have to be addressable too
+    assert!(x.commit_contiguous(0x80_0000_0000).is_err());
+    assert!(x.commit_contiguous(0x10_0000).is_ok());
+}
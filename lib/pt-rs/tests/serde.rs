@@ -0,0 +1,175 @@
+#![cfg(feature = "serde")]
+
+extern crate pt;
+
+use pt::{Flags, MemoryType, PageTable, Paging, PAT_DEFAULT};
+
+// WB, WC, UC-, UC, WB, WP, UC-, WT
+const PAT: u64 = 0x0407_0506_0007_0106;
+
+fn round_trip(x: &PageTable) -> PageTable {
+    let s = serde_json::to_string(x).unwrap();
+    serde_json::from_str(&s).unwrap()
+}
+
+#[test]
+fn round_trips() {
+    let mut x = PageTable::with_phys_bits(Paging::Level4, 46).unwrap();
+    let wc = Flags::Present
+        .with_memory_type(MemoryType::WriteCombining, PAT)
+        .unwrap();
+    let wp = Flags::Present
+        .with_memory_type(MemoryType::WriteProtected, PAT)
+        .unwrap();
+
+    x.set_pat(PAT);
+    x.map_range(
+        0x4141_0000,
+        0x8181_0000,
+        0x3000,
+        Flags::Present | Flags::Writable | Flags::User,
+    )
+    .unwrap();
+    x.insert(0x4141_5000, 0xc000_0000, wc).unwrap();
+    x.insert_large(0x4160_0000, 0x20_0000, wp | Flags::NX)
+        .unwrap();
+    x.insert_huge(
+        0xffff_8000_0000_0000,
+        0x4000_0000,
+        Flags::Present.with_key(5) | Flags::Global,
+    )
+    .unwrap();
+
+    let y = round_trip(&x);
+
+    assert_eq!(y.paging(), Paging::Level4);
+    assert_eq!(y.phys_bits(), 46);
+    assert_eq!(y.pat(), PAT);
+    assert!(x.diff(&y).is_empty());
+
+    // the same pages, not just the same ranges
+    let pages: Vec<_> = x.mappings().collect();
+    assert_eq!(y.mappings().collect::<Vec<_>>(), pages);
+
+    let m = y.mappings().find(|m| m.vaddr == 0x4141_5000).unwrap();
+    assert_eq!(m.memory_type(PAT), Some(MemoryType::WriteCombining));
+    let m = y.mappings().find(|m| m.vaddr == 0x4160_0000).unwrap();
+    assert_eq!(m.memory_type(PAT), Some(MemoryType::WriteProtected));
+
+    let m = y.mappings().last().unwrap();
+    assert_eq!(m.flags.key(), 5);
+    assert!(m.flags.contains(Flags::Global));
+
+    assert_eq!(
+        x.commit_at(0x10_0000).unwrap().1.len(),
+        y.commit_at(0x10_0000).unwrap().1.len()
+    );
+}
+
+#[test]
+fn la57() {
+    let mut x = PageTable::new(Paging::Level5);
+    x.insert(0xff00_0000_0000_0000, 0x1000, Flags::Present)
+        .unwrap();
+
+    let y = round_trip(&x);
+    assert_eq!(y.paging(), Paging::Level5);
+    assert!(x.diff(&y).is_empty());
+}
+
+#[test]
+fn fields() {
+    let mut x = PageTable::default();
+    let uc = (Flags::Present | Flags::Writable)
+        .with_memory_type(MemoryType::Uncacheable, PAT)
+        .unwrap();
+    let wc = Flags::Present
+        .with_memory_type(MemoryType::WriteCombining, PAT)
+        .unwrap();
+    let wp = Flags::Present
+        .with_memory_type(MemoryType::WriteProtected, PAT)
+        .unwrap();
+
+    x.set_pat(PAT);
+    x.insert(0x4141_4000, 0xfee0_0000, uc).unwrap();
+    x.insert(0x4141_5000, 0xc000_0000, wc).unwrap();
+    x.insert_large(0x4160_0000, 0x20_0000, wp).unwrap();
+
+    let v = serde_json::to_value(x).unwrap();
+    let m = &v["mappings"][0];
+
+    assert_eq!(v["paging"], "Level4");
+    assert_eq!(v["pat"], PAT);
+    assert_eq!(m["vaddr"], 0x4141_4000);
+    assert_eq!(m["paddr"], 0xfee0_0000u64);
+    assert_eq!(m["size"], 0x1000);
+    assert_eq!(m["flags"], (Flags::Present | Flags::Writable).bits());
+    assert_eq!(m["memory_type"], "Uncacheable");
+    assert_eq!(m["pkey"], 0);
+
+    // types which PWT and PCD alone can't select in the default PAT
+    assert_eq!(v["mappings"][1]["memory_type"], "WriteCombining");
+    assert_eq!(v["mappings"][2]["memory_type"], "WriteProtected");
+}
+
+#[test]
+fn invalid() {
+    let mut x = PageTable::default();
+
+    x.set_pat(PAT);
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    // encoding 2 in the first entry is reserved
+    x.set_pat(PAT & !0xff | 2);
+    assert!(serde_json::to_value(&x).is_err());
+
+    x.set_pat(PAT);
+    let mut v = serde_json::to_value(x).unwrap();
+
+    v["mappings"][0]["size"] = 0x2000.into();
+    let e = serde_json::from_value::<PageTable>(v.clone())
+        .err()
+        .unwrap();
+    assert!(e.to_string().contains("0x2000 is not a page size"));
+
+    v["mappings"][0]["size"] = 0x1000.into();
+    v["mappings"][0]["vaddr"] = 0x4141_0800.into();
+    let e = serde_json::from_value::<PageTable>(v.clone())
+        .err()
+        .unwrap();
+    assert!(e.to_string().contains("not page aligned"));
+
+    v["mappings"][0]["vaddr"] = 0x4141_0000.into();
+    v["pat"] = PAT_DEFAULT.into();
+    v["mappings"][0]["memory_type"] = "WriteCombining".into();
+    let e = serde_json::from_value::<PageTable>(v.clone())
+        .err()
+        .unwrap();
+    assert!(e.to_string().contains("WriteCombining isn't in the PAT"));
+
+    v["pat"] = PAT.into();
+    v["mappings"][0]["memory_type"] = "WriteBack".into();
+    v["phys_bits"] = 53.into();
+    assert!(serde_json::from_value::<PageTable>(v).is_err());
+}
+
+#[test]
+fn reserved() {
+    let mut x = PageTable::default();
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+    x.insert(0x4141_1000, 0x80_0000_0000, Flags::Present)
+        .unwrap();
+
+    // bit 39 is reserved with 39 bits of physical address, so the second
+    // page isn't mapped
+    let region = x.commit_contiguous(0x10_0000).unwrap();
+    let x = PageTable::from_physical_with(Paging::Level4, 39, region.root(), |gpa, buf| {
+        region.read(gpa, buf)
+    })
+    .unwrap();
+    assert_eq!(x.mappings().count(), 1);
+
+    let y = round_trip(&x);
+    assert_eq!(y.phys_bits(), 39);
+    assert!(x.diff(&y).is_empty());
+}
@@ -0,0 +1,107 @@
+extern crate pt;
+
+use pt::{Context, Flags, PageTable, Prot, Tlb};
+
+#[test]
+fn cached() {
+    let mut x = PageTable::default();
+    let mut tlb = Tlb::new();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    assert_eq!(tlb.translate(&x, 0x4141_0123, Prot::R), Some(0x8181_0123));
+
+    // the stale translation is used until the page is invalidated
+    x.insert(0x4141_0000, 0x9191_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    assert_eq!(tlb.translate(&x, 0x4141_0123, Prot::R), Some(0x8181_0123));
+
+    // a different access type misses
+    assert_eq!(tlb.translate(&x, 0x4141_0123, Prot::W), Some(0x9191_0123));
+
+    tlb.invlpg(0x4141_0fff);
+    assert_eq!(tlb.translate(&x, 0x4141_0123, Prot::R), Some(0x9191_0123));
+}
+
+#[test]
+fn faults() {
+    let mut x = PageTable::default();
+    let mut tlb = Tlb::new();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present).unwrap();
+
+    // faults aren't cached
+    assert_eq!(tlb.translate(&x, 0x4141_0000, Prot::W), None);
+    x.protect(0x4141_0000, 0x1000, Flags::Present | Flags::Writable)
+        .unwrap();
+    assert_eq!(tlb.translate(&x, 0x4141_0000, Prot::W), Some(0x8181_0000));
+
+    // nor are translations shared between contexts
+    assert_eq!(
+        tlb.translate_with(&x, 0x4141_0000, Prot::R, &Context::user()),
+        None
+    );
+}
+
+#[test]
+fn large() {
+    let mut x = PageTable::default();
+    let mut tlb = Tlb::new();
+
+    x.insert_large(0x4140_0000, 0x20_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert_huge(0x8000_0000, 0x4000_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    assert_eq!(tlb.translate(&x, 0x4141_0000, Prot::R), Some(0x21_0000));
+    assert_eq!(tlb.translate(&x, 0x8123_4567, Prot::R), Some(0x4123_4567));
+
+    x.protect(0x4140_0000, 0x20_0000, Flags::empty()).unwrap();
+    x.protect(0x8000_0000, 0x4000_0000, Flags::empty()).unwrap();
+
+    // the cached pages cover their whole size
+    assert_eq!(tlb.translate(&x, 0x415f_f000, Prot::R), Some(0x3f_f000));
+    assert_eq!(tlb.translate(&x, 0xbfff_f000, Prot::R), Some(0x7fff_f000));
+
+    // and any address in them invalidates them
+    tlb.invlpg(0x415f_f000);
+    tlb.invlpg(0x8000_0000);
+    assert_eq!(tlb.translate(&x, 0x4141_0000, Prot::R), None);
+    assert_eq!(tlb.translate(&x, 0x8123_4567, Prot::R), None);
+}
+
+#[test]
+fn global() {
+    let mut x = PageTable::default();
+    let mut tlb = Tlb::new();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(
+        0xffff_8000_0000_0000,
+        0x1000,
+        Flags::Present | Flags::Writable | Flags::Global,
+    )
+    .unwrap();
+
+    for vaddr in [0x4141_0000, 0x4141_1000, 0xffff_8000_0000_0000].iter() {
+        assert!(tlb.translate(&x, *vaddr, Prot::R).is_some());
+    }
+
+    let mut y = PageTable::default();
+    y.insert(0x4141_0000, 0x9191_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    // switching tables keeps the global page
+    tlb.flush();
+    assert_eq!(tlb.translate(&y, 0x4141_0000, Prot::R), Some(0x9191_0000));
+    assert_eq!(tlb.translate(&y, 0x4141_1000, Prot::R), None);
+    assert_eq!(
+        tlb.translate(&y, 0xffff_8000_0000_0000, Prot::R),
+        Some(0x1000)
+    );
+
+    tlb.flush_all();
+    assert_eq!(tlb.translate(&y, 0xffff_8000_0000_0000, Prot::R), None);
+}
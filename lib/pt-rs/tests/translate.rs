@@ -6,7 +6,7 @@ use pt::{Flags, PageTable, Prot};
 fn translate_none() {
     let mut x = PageTable::default();
 
-    x.insert(0x7171_0000, 0, Flags::NX | Flags::Present);
+    x.insert(0x7171_0000, 0, Flags::NX | Flags::Present).unwrap();
     assert_eq!(x.translate(0x8181_0000, Prot::R), None);
 }
 
@@ -14,7 +14,7 @@ fn translate_none() {
 fn translate_read() {
     let mut x = PageTable::default();
 
-    x.insert(0x4141_4000, 0, Flags::NX | Flags::Present);
+    x.insert(0x4141_4000, 0, Flags::NX | Flags::Present).unwrap();
 
     assert_eq!(x.translate(0x4141_4000, Prot::R).unwrap(), 0);
     assert_eq!(x.translate(0x4141_4000, Prot::W), None);
@@ -25,7 +25,7 @@ fn translate_read() {
 fn translate_write() {
     let mut x = PageTable::default();
 
-    x.insert(0x8_1818_1000, 0, Flags::NX | Flags::Present | Flags::Writable);
+    x.insert(0x8_1818_1000, 0, Flags::NX | Flags::Present | Flags::Writable).unwrap();
 
     assert_eq!(x.translate(0x8_1818_1000, Prot::R).unwrap(), 0);
     assert_eq!(x.translate(0x8_1818_1000, Prot::W).unwrap(), 0);
@@ -36,7 +36,7 @@ fn translate_write() {
 fn translate_execute() {
     let mut x = PageTable::default();
 
-    x.insert(0x4141_4000, 0, Flags::Present);
+    x.insert(0x4141_4000, 0, Flags::Present).unwrap();
 
     assert_eq!(x.translate(0x4141_4000, Prot::R).unwrap(), 0);
     assert_eq!(x.translate(0x4141_4000, Prot::W), None);
@@ -47,7 +47,7 @@ fn translate_execute() {
 fn translate_all() {
     let mut x = PageTable::default();
 
-    x.insert(0x4141_4000, 0, Flags::Present | Flags::Writable);
+    x.insert(0x4141_4000, 0, Flags::Present | Flags::Writable).unwrap();
 
     assert_eq!(x.translate(0x4141_4000, Prot::R).unwrap(), 0);
     assert_eq!(x.translate(0x4141_4000, Prot::W).unwrap(), 0);
@@ -0,0 +1,99 @@
+extern crate pt;
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+
+use pt::{AccessFault, Context, Flags, Level, PageFault, PageTable, PfCode, PhysMem};
+
+// sparse guest memory, pages are created on first write
+#[derive(Default)]
+struct Mem {
+    pages: BTreeMap<u64, [u8; 0x1000]>,
+}
+
+impl PhysMem for Mem {
+    fn read(&self, gpa: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let page = self
+            .pages
+            .get(&(gpa & !0xfff))
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))?;
+        let off = (gpa & 0xfff) as usize;
+
+        buf.copy_from_slice(&page[off..off + buf.len()]);
+        Ok(())
+    }
+
+    fn write(&mut self, gpa: u64, buf: &[u8]) -> Result<(), Error> {
+        let page = self.pages.entry(gpa & !0xfff).or_insert([0; 0x1000]);
+        let off = (gpa & 0xfff) as usize;
+
+        page[off..off + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[test]
+fn split() {
+    let mut x = PageTable::default();
+    let mut mem = Mem::default();
+
+    // physically discontiguous
+    x.insert(0x4141_0000, 0x8181_3000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+
+    let data: Vec<u8> = (0..0x100).map(|x| x as u8).collect();
+    x.virt_write(&mut mem, 0x4141_0f80, &data).unwrap().unwrap();
+
+    assert_eq!(&mem.pages[&0x8181_3000][0xf80..], &data[..0x80]);
+    assert_eq!(&mem.pages[&0x8181_0000][..0x80], &data[0x80..]);
+
+    let mut buf = vec![0; 0x100];
+    x.virt_read(&mem, 0x4141_0f80, &mut buf).unwrap().unwrap();
+    assert_eq!(buf, data);
+}
+
+#[test]
+fn faults() {
+    let mut x = PageTable::default();
+    let mut mem = Mem::default();
+
+    x.insert(0x4141_0000, 0x8181_0000, Flags::Present | Flags::Writable)
+        .unwrap();
+    x.insert(0x4141_1000, 0x8181_1000, Flags::Present).unwrap();
+
+    // the first faulting address is reported, and nothing is written
+    assert_eq!(
+        x.virt_write(&mut mem, 0x4141_0ff0, &[0x41; 0x20]).unwrap(),
+        Err(AccessFault {
+            vaddr: 0x4141_1000,
+            fault: PageFault {
+                code: PfCode::Present | PfCode::Write,
+                level: Level::Pt,
+            },
+        })
+    );
+    assert!(mem.pages.is_empty());
+
+    let mut buf = [0; 0x10];
+    assert_eq!(
+        x.virt_read(&mem, 0x4141_1ff8, &mut buf).unwrap(),
+        Err(AccessFault {
+            vaddr: 0x4141_2000,
+            fault: PageFault {
+                code: PfCode::empty(),
+                level: Level::Pt,
+            },
+        })
+    );
+
+    // user accesses to supervisor pages
+    assert!(x
+        .virt_read_with(&mem, 0x4141_0000, &mut buf, &Context::user())
+        .unwrap()
+        .is_err());
+
+    // and failed reads of the memory itself
+    assert!(x.virt_read(&mem, 0x4141_0000, &mut buf).is_err());
+}
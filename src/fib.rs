@@ -1,4 +1,3 @@
-use std::mem;
 use std::os::raw::c_void;
 use std::time::Instant;
 
@@ -21,13 +20,14 @@ unsafe fn fib() {
     let mut pt = PageTable::default();
 
     println!("creating a mapping from gva 0x41410000 to gpa 0x81810000 for the text...");
-    pt.insert(0x4141_0000, 0x8181_0000, Flags::User | Flags::Present);
+    pt.insert(0x4141_0000, 0x8181_0000, Flags::User | Flags::Present).unwrap();
     println!("creating a mapping from gva 0x12345000 to gpa 0x67890000 for the stack...");
     pt.insert(
         0x1234_5000,
         0x6789_0000,
         Flags::User | Flags::Present | Flags::Writable,
-    );
+    )
+    .unwrap();
 
     // we need to map in our phys mem for the mappings we created above
     let mut code_backing = MmapMut::map_anon(0x1000).unwrap();
@@ -43,23 +43,18 @@ unsafe fn fib() {
     );
     guest_mem::page_insert(0x6789_0000, stack_backing.as_mut_ptr());
 
-    // serialize our page tables
-    let (pml4, mut pagetable) = pt.commit().unwrap();
+    // serialize our page tables into one host region, which stays mapped
+    // until it's dropped at the end of the run
+    let mut tables = pt.commit_contiguous(0).unwrap();
+    let pml4 = tables.root();
     println!("page table serialized, base @ {:#x}", pml4);
 
     // write our page tables into our guest
-    for (gpa, hva) in pagetable.iter_mut() {
-        println!(
-            "mapping page table gpa {:#x} to hva {:p}...",
-            gpa,
-            hva.as_ptr()
-        );
-
-        guest_mem::page_insert(*gpa, hva.as_mut_ptr());
+    for (gpa, hva) in tables.pages() {
+        println!("mapping page table gpa {:#x} to hva {:p}...", gpa, hva);
+
+        guest_mem::page_insert(gpa, hva);
     }
-    // if we let the mem mapping go out of scope it will be dropped and
-    // the backing page unmapped, so we'll just leak it
-    mem::forget(pagetable);
 
     println!("setting up cpu registers...");
     let c = Cpu::new(0);